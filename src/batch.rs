@@ -0,0 +1,41 @@
+use crate::error::HeatzyError;
+
+/// Per-device outcome of an operation run on several devices
+///
+/// Devices are listed in the order their operation completed.
+#[derive(Debug)]
+pub struct BatchReport<T = ()> {
+    /// Devices the operation succeeded on, with its result
+    pub successes: Vec<(String, T)>,
+    /// Devices the operation failed on, with the error
+    pub failures: Vec<(String, HeatzyError)>,
+    /// Devices left out because they are offline
+    pub skipped_offline: Vec<String>,
+}
+
+impl<T> Default for BatchReport<T> {
+    fn default() -> Self {
+        Self {
+            successes: Vec::new(),
+            failures: Vec::new(),
+            skipped_offline: Vec::new(),
+        }
+    }
+}
+
+impl<T> BatchReport<T> {
+    /// Check whether the operation succeeded on every device
+    pub fn all_succeeded(&self) -> bool {
+        self.failures.is_empty() && self.skipped_offline.is_empty()
+    }
+
+    /// Number of devices in the report
+    pub fn len(&self) -> usize {
+        self.successes.len() + self.failures.len() + self.skipped_offline.len()
+    }
+
+    /// Check whether the report covers no device
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
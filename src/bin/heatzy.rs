@@ -1,8 +1,12 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use flexi_logger::{Logger, WriteMode};
-use heatzy::{Client, DeviceMode};
-use log::{debug, error};
+use futures::StreamExt;
+use heatzy::{BindingReport, Client, ControlAttributes, DerogationMode, Device, DeviceEvent, DeviceMode, FileTokenStore, ListDevicesOptions, NewScheduledTask, Region, Repeat, ScheduleSlot, Session, Setpoints, ShareTarget, SharingRole, TaskAction, Temperature, TokenStore, UserUpdate, Weekday, WeeklySchedule};
+use log::{debug, error, warn};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -11,6 +15,18 @@ struct Cli {
     #[arg(short, long, global = true)]
     token: Option<String>,
     
+    /// Account whose cached token to use (defaults to the only cached account)
+    #[arg(long, global = true)]
+    account: Option<String>,
+    
+    /// Gizwits region (eu, us, cn)
+    #[arg(long, default_value = "eu", global = true)]
+    region: String,
+    
+    /// Override the API base URL
+    #[arg(long, global = true)]
+    base_url: Option<String>,
+    
     /// Log level (error, warn, info, debug, trace)
     #[arg(long, default_value = "warn", global = true)]
     log_level: String,
@@ -19,57 +35,907 @@ struct Cli {
     command: Commands,
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    /// Login and get authentication token
-    Login {
-        /// Username (email)
-        #[arg(short, long)]
-        username: String,
-        
-        /// Password
-        #[arg(short, long)]
-        password: String,
-    },
-    
-    /// List all devices
-    Devices,
-    
-    /// Get device information
-    Device {
-        /// Device name
-        #[arg(long = "name", group = "device")]
-        device_name: Option<String>,
-        
-        /// Device ID
-        #[arg(long = "id", group = "device")]
-        device_id: Option<String>,
-    },
-    
-    /// Get current device mode
-    GetMode {
-        /// Device name
-        #[arg(long = "name", group = "device")]
-        device_name: Option<String>,
-        
-        /// Device ID
-        #[arg(long = "id", group = "device")]
-        device_id: Option<String>,
-    },
+#[derive(Subcommand)]
+enum Commands {
+    /// Login and get authentication token
+    Login {
+        /// Username (email)
+        #[arg(short, long)]
+        username: String,
+        
+        /// Password
+        #[arg(short, long)]
+        password: String,
+    },
+    
+    /// Remove the cached token of an account
+    Logout,
+    
+    /// Register, show or change an account
+    Account {
+        #[command(subcommand)]
+        action: AccountCommands,
+    },
+    
+    /// List all devices
+    Devices {
+        /// Include disabled devices
+        #[arg(long)]
+        show_disabled: bool,
+        
+        /// Only list devices of this product key
+        #[arg(long)]
+        product_key: Option<String>,
+    },
+    
+    /// Get device information
+    Device {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+    },
+    
+    /// Get current device mode
+    GetMode {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+    },
+    
+    /// Get the full state reported by a device
+    State {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+    },
+    
+    /// Set device mode
+    SetMode {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// Mode (comfort, eco, frost-protection, stop, comfort-1, comfort-2)
+        mode: String,
+    },
+    
+    /// Start a comfort boost
+    Boost {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// Duration in minutes
+        minutes: u64,
+    },
+    
+    /// Switch to frost protection while away
+    Vacation {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// Duration in days
+        days: u32,
+    },
+    
+    /// Cancel a running boost, vacation or presence detection
+    CancelDerogation {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+    },
+    
+    /// Show or change the child lock
+    Lock {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// New state (on, off)
+        state: Option<String>,
+    },
+    
+    /// Show or change open-window detection
+    WindowDetection {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// New state (on, off)
+        state: Option<String>,
+    },
+    
+    /// Show the ambient temperature and humidity
+    Sensors {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+    },
+    
+    /// Show or change the comfort and eco setpoints
+    Setpoint {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// Comfort temperature in °C
+        #[arg(long)]
+        comfort: Option<f32>,
+        
+        /// Eco temperature in °C
+        #[arg(long)]
+        eco: Option<f32>,
+    },
+    
+    /// Show, change or export the weekly programme
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleCommands,
+    },
+    
+    /// Bind a device to the account, by ID and passcode or by MAC address
+    Bind {
+        /// Device ID
+        #[arg(long = "id", group = "target", requires = "passcode")]
+        device_id: Option<String>,
+        
+        /// Device passcode
+        #[arg(long, requires = "device_id")]
+        passcode: Option<String>,
+        
+        /// Device MAC address
+        #[arg(long, group = "target", requires_all = ["product_key", "product_secret"])]
+        mac: Option<String>,
+        
+        /// Product key of the device
+        #[arg(long, requires = "mac")]
+        product_key: Option<String>,
+        
+        /// Product secret used to sign the request
+        #[arg(long, requires = "mac")]
+        product_secret: Option<String>,
+        
+        /// Name given to the device
+        #[arg(long, requires = "device_id")]
+        alias: Option<String>,
+        
+        /// Free text stored with the binding
+        #[arg(long, requires = "device_id")]
+        remark: Option<String>,
+    },
+    
+    /// Unbind devices from the account
+    Unbind {
+        /// Device name, can be repeated
+        #[arg(long = "name")]
+        device_names: Vec<String>,
+        
+        /// Device ID, can be repeated
+        #[arg(long = "id")]
+        device_ids: Vec<String>,
+    },
+    
+    /// Rename a device
+    Rename {
+        /// Current device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// New name
+        alias: String,
+        
+        /// Also replace the remark stored with the device
+        #[arg(long)]
+        remark: Option<String>,
+    },
+    
+    /// Manage device groups and control them at once
+    Group {
+        #[command(subcommand)]
+        action: GroupCommands,
+    },
+    
+    /// Share devices with other accounts
+    Share {
+        #[command(subcommand)]
+        action: ShareCommands,
+    },
+    
+    /// Manage tasks run by the Gizwits cloud scheduler
+    Timer {
+        #[command(subcommand)]
+        action: TimerCommands,
+    },
+    
+    /// Print device changes in real time until interrupted
+    Listen {
+        /// Device name, can be repeated (defaults to all devices)
+        #[arg(long = "name")]
+        device_names: Vec<String>,
+        
+        /// Device ID, can be repeated (defaults to all devices)
+        #[arg(long = "id")]
+        device_ids: Vec<String>,
+        
+        /// Poll the devices every SECONDS instead of using the websocket
+        #[arg(long, value_name = "SECONDS")]
+        poll: Option<u64>,
+        
+        /// User id of the --token account, needed by the websocket
+        #[arg(long)]
+        uid: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AccountCommands {
+    /// Create an account and cache its token
+    Register {
+        /// Username (email)
+        #[arg(short, long)]
+        username: String,
+        
+        /// Password
+        #[arg(short, long)]
+        password: String,
+    },
+    
+    /// Show the account details
+    Info,
+    
+    /// Change the account details
+    Update {
+        /// Email address
+        #[arg(long)]
+        email: Option<String>,
+        
+        /// Phone number
+        #[arg(long)]
+        phone: Option<String>,
+        
+        /// Display name
+        #[arg(long)]
+        name: Option<String>,
+        
+        /// Preferred language (e.g. en, fr)
+        #[arg(long)]
+        lang: Option<String>,
+        
+        /// Free text stored with the account
+        #[arg(long)]
+        remark: Option<String>,
+    },
+    
+    /// Change the account password
+    ChangePassword {
+        /// Current password
+        #[arg(long)]
+        old: String,
+        
+        /// New password
+        #[arg(long)]
+        new: String,
+    },
+    
+    /// Send a password reset email
+    ResetPassword {
+        /// Email address of the account
+        #[arg(long)]
+        email: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ScheduleCommands {
+    /// Show the programme as a weekly grid
+    Show {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+    },
+    
+    /// Replace the programme from a file
+    Set {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// Programme file, `-` for stdin
+        #[arg(long)]
+        file: PathBuf,
+        
+        /// File format (text, json, yaml), guessed from the extension by default
+        #[arg(long)]
+        format: Option<String>,
+        
+        /// Also enable the programme
+        #[arg(long)]
+        enable: bool,
+    },
+    
+    /// Write the programme to a file or stdout
+    Export {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// File format (text, json, yaml), guessed from the extension by default
+        #[arg(long)]
+        format: Option<String>,
+        
+        /// Output file, stdout by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum GroupCommands {
+    /// List device groups
+    List,
+    
+    /// Create a device group
+    Create {
+        /// Group name
+        name: String,
+        
+        /// Only accept devices of this product key
+        #[arg(long)]
+        product_key: Option<String>,
+    },
+    
+    /// Rename a device group
+    Rename {
+        /// Group ID
+        group_id: String,
+        
+        /// New name
+        name: String,
+    },
+    
+    /// Delete a device group
+    Delete {
+        /// Group ID
+        group_id: String,
+    },
+    
+    /// List the devices of a group
+    Devices {
+        /// Group ID
+        group_id: String,
+    },
+    
+    /// Add devices to a group
+    Add {
+        /// Group ID
+        group_id: String,
+        
+        /// Device name, can be repeated
+        #[arg(long = "name")]
+        device_names: Vec<String>,
+        
+        /// Device ID, can be repeated
+        #[arg(long = "id")]
+        device_ids: Vec<String>,
+    },
+    
+    /// Remove devices from a group
+    Remove {
+        /// Group ID
+        group_id: String,
+        
+        /// Device name, can be repeated
+        #[arg(long = "name")]
+        device_names: Vec<String>,
+        
+        /// Device ID, can be repeated
+        #[arg(long = "id")]
+        device_ids: Vec<String>,
+    },
+    
+    /// Set the mode of every device of a group
+    SetMode {
+        /// Group ID
+        group_id: String,
+        
+        /// Mode (comfort, eco, frost-protection, stop, comfort-1, comfort-2)
+        mode: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ShareCommands {
+    /// Invite an account, or create a QR code, to share a device
+    Create {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// User ID of the guest
+        #[arg(long, group = "target")]
+        uid: Option<String>,
+        
+        /// Username of the guest
+        #[arg(long, group = "target")]
+        username: Option<String>,
+        
+        /// Email address of the guest
+        #[arg(long, group = "target")]
+        email: Option<String>,
+        
+        /// Phone number of the guest
+        #[arg(long, group = "target")]
+        phone: Option<String>,
+        
+        /// Create a QR code valid for this many minutes
+        #[arg(long, group = "target")]
+        qr_minutes: Option<u32>,
+    },
+    
+    /// List sharings sent by the account, or received with --received
+    List {
+        /// List invitations received instead of sent
+        #[arg(long)]
+        received: bool,
+    },
+    
+    /// Accept a received invitation, by ID or QR code content
+    Accept {
+        /// Sharing ID
+        #[arg(group = "sharing")]
+        sharing_id: Option<String>,
+        
+        /// Content of the QR code
+        #[arg(long, group = "sharing")]
+        code: Option<String>,
+    },
+    
+    /// Refuse a received invitation
+    Refuse {
+        /// Sharing ID
+        sharing_id: String,
+    },
+    
+    /// Cancel a sent invitation
+    Revoke {
+        /// Sharing ID
+        sharing_id: String,
+    },
+    
+    /// List the accounts a device is shared with
+    Guests {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+    },
+    
+    /// Remove the access of an account to a device
+    RemoveGuest {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        /// User ID of the guest
+        uid: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TimerCommands {
+    /// List scheduler tasks
+    List {
+        /// Only list tasks of this device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Only list tasks of this device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+    },
+    
+    /// Create a scheduler task
+    Create {
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        #[command(flatten)]
+        task: TimerArgs,
+    },
+    
+    /// Replace a scheduler task
+    Update {
+        /// Task ID
+        task_id: String,
+        
+        /// Device name
+        #[arg(long = "name", group = "device")]
+        device_name: Option<String>,
+        
+        /// Device ID
+        #[arg(long = "id", group = "device")]
+        device_id: Option<String>,
+        
+        #[command(flatten)]
+        task: TimerArgs,
+    },
+    
+    /// Delete a scheduler task
+    Delete {
+        /// Task ID
+        task_id: String,
+    },
+}
+
+/// Definition of a scheduler task
+#[derive(Args)]
+struct TimerArgs {
+    /// Time of day in UTC (HH:MM)
+    #[arg(long)]
+    time: String,
+    
+    /// Run once on this date (YYYY-MM-DD)
+    #[arg(long, group = "repeat")]
+    date: Option<String>,
+    
+    /// Run every week on these days (e.g. mon,wed,fri)
+    #[arg(long, group = "repeat")]
+    days: Option<String>,
+    
+    /// Run every day
+    #[arg(long, group = "repeat")]
+    daily: bool,
+    
+    /// Run every month on these days (e.g. 1,15)
+    #[arg(long, group = "repeat")]
+    monthly: Option<String>,
+    
+    /// Mode to switch to (comfort, eco, frost-protection, stop, comfort-1, comfort-2)
+    #[arg(long, group = "action")]
+    mode: Option<String>,
+    
+    /// Attribute to write as name=value, can be repeated
+    #[arg(long = "attr", group = "action")]
+    attrs: Vec<String>,
+    
+    /// Free text stored with the task
+    #[arg(long)]
+    remark: Option<String>,
+    
+    /// Create the task disabled
+    #[arg(long)]
+    disabled: bool,
+}
+
+impl TimerArgs {
+    /// Build the task from the CLI arguments
+    fn to_task(&self) -> Result<NewScheduledTask> {
+        let repeat = if let Some(date) = &self.date {
+            Repeat::Once(date.clone())
+        } else if let Some(days) = &self.days {
+            let days = days.split(',')
+                .map(|day| Weekday::from_cli_str(day.trim()))
+                .collect::<Result<_, _>>()
+                .context("Invalid days")?;
+            Repeat::Weekly(days)
+        } else if self.daily {
+            Repeat::daily()
+        } else if let Some(days) = &self.monthly {
+            let days = days.split(',')
+                .map(|day| day.trim().parse::<u8>())
+                .collect::<Result<_, _>>()
+                .context("Invalid days of the month")?;
+            Repeat::Monthly(days)
+        } else {
+            anyhow::bail!("Specify when the task runs with --date, --days, --daily or --monthly");
+        };
+        
+        let action = if let Some(mode) = &self.mode {
+            TaskAction::Mode(DeviceMode::from_cli_str(mode).context("Invalid mode")?)
+        } else if !self.attrs.is_empty() {
+            let mut attrs = ControlAttributes::new();
+            for attr in &self.attrs {
+                let (name, value) = attr.split_once('=')
+                    .with_context(|| format!("Invalid attribute '{}', expected name=value", attr))?;
+                // Numbers and booleans are sent as such, anything else as a string
+                let value = serde_json::from_str(value)
+                    .unwrap_or_else(|_| serde_json::Value::from(value));
+                attrs.insert(name, value);
+            }
+            TaskAction::Attributes(attrs)
+        } else {
+            anyhow::bail!("Specify what the task does with --mode or --attr");
+        };
+        
+        Ok(NewScheduledTask {
+            time: self.time.clone(),
+            repeat,
+            action,
+            enabled: !self.disabled,
+            remark: self.remark.clone(),
+        })
+    }
+}
+
+/// Serialisation of a programme file
+#[derive(Clone, Copy)]
+enum ScheduleFileFormat {
+    Text,
+    Json,
+    Yaml,
+}
+
+impl ScheduleFileFormat {
+    /// Pick the format from --format, or from the file extension
+    fn resolve(format: Option<&str>, path: Option<&Path>) -> Result<Self> {
+        if let Some(format) = format {
+            return match format.to_lowercase().as_str() {
+                "text" | "txt" => Ok(ScheduleFileFormat::Text),
+                "json" => Ok(ScheduleFileFormat::Json),
+                "yaml" | "yml" => Ok(ScheduleFileFormat::Yaml),
+                _ => anyhow::bail!("Invalid format: {}. Valid formats are: text, json, yaml", format),
+            };
+        }
+        
+        let extension = path
+            .and_then(Path::extension)
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("json") => Ok(ScheduleFileFormat::Json),
+            Some("yaml" | "yml") => Ok(ScheduleFileFormat::Yaml),
+            _ => Ok(ScheduleFileFormat::Text),
+        }
+    }
+    
+    /// Read a programme
+    fn parse(&self, content: &str) -> Result<WeeklySchedule> {
+        match self {
+            ScheduleFileFormat::Text => content.parse().context("Invalid schedule"),
+            ScheduleFileFormat::Json => serde_json::from_str(content).context("Invalid JSON schedule"),
+            ScheduleFileFormat::Yaml => serde_yaml::from_str(content).context("Invalid YAML schedule"),
+        }
+    }
+    
+    /// Write a programme
+    fn format(&self, schedule: &WeeklySchedule) -> Result<String> {
+        match self {
+            ScheduleFileFormat::Text => Ok(schedule.to_string()),
+            ScheduleFileFormat::Json => Ok(serde_json::to_string_pretty(schedule)? + "\n"),
+            ScheduleFileFormat::Yaml => Ok(serde_yaml::to_string(schedule)?),
+        }
+    }
+}
+
+/// Print a programme as one row of half-hour slots per day
+fn print_schedule_grid(schedule: &WeeklySchedule) {
+    let header: String = (0..24).step_by(3).map(|hour| format!("{:<6}", hour)).collect();
+    println!("    {}", header.trim_end());
+    
+    for day in Weekday::ALL {
+        let row: String = schedule.day(day).iter()
+            .map(|slot| match slot {
+                ScheduleSlot::Comfort => 'C',
+                ScheduleSlot::Eco => 'E',
+                ScheduleSlot::FrostProtection => 'F',
+                ScheduleSlot::Off => '.',
+            })
+            .collect();
+        println!("{} {}", day, row);
+    }
+    println!("C comfort, E eco, F frost protection, . off");
+}
+
+/// Create a client from the global CLI options
+fn build_client(cli: &Cli, token_store: Option<Arc<FileTokenStore>>) -> Result<Client> {
+    let region = Region::from_cli_str(&cli.region).context("Invalid region")?;
+    let mut builder = Client::builder().region(region);
+    
+    if let Some(token_store) = token_store {
+        builder = builder.token_store(token_store);
+    }
+    if let Some(base_url) = &cli.base_url {
+        builder = builder.base_url(base_url);
+    }
+    
+    builder.build().context("Failed to create client")
+}
+
+/// Open the token cache, carrying on without one when it cannot be located
+fn open_token_store() -> Option<Arc<FileTokenStore>> {
+    match FileTokenStore::default_location() {
+        Ok(token_store) => Some(Arc::new(token_store)),
+        Err(e) => {
+            warn!("Tokens will not be cached: {}", e);
+            None
+        }
+    }
+}
+
+/// Pick the account from --account, or the only account cached for the region
+fn resolve_account(cli: &Cli, client: &Client, token_store: Option<&FileTokenStore>) -> Result<Option<String>> {
+    if let Some(account) = &cli.account {
+        return Ok(Some(account.clone()));
+    }
+    let Some(token_store) = token_store else {
+        return Ok(None);
+    };
+    
+    let mut accounts: Vec<String> = token_store.keys()
+        .context("Failed to read token cache")?
+        .into_iter()
+        .filter(|key| *key == client.token_key(&key.account))
+        .map(|key| key.account)
+        .collect();
+    
+    if accounts.len() > 1 {
+        error!("Several accounts are cached, select one with --account: {}", accounts.join(", "));
+        std::process::exit(1);
+    }
+    Ok(accounts.pop())
+}
+
+/// Parse an on/off CLI argument
+fn parse_switch(value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => anyhow::bail!("Invalid state: {}. Valid states are: on, off", value),
+    }
+}
+
+/// Fetch a device from either --name or --id
+async fn resolve_device(client: &Client, device_name: Option<String>, device_id: Option<String>) -> Result<Device> {
+    match (device_name, device_id) {
+        (Some(name), None) => {
+            client.get_device_by_name(&name).await
+                .context("Failed to get device by name")
+        }
+        (None, Some(id)) => {
+            client.get_device(&id).await
+                .context("Failed to get device by ID")
+        }
+        _ => {
+            error!("Must specify either --name or --id");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Get a device ID from either --name or --id, looking the name up if needed
+async fn resolve_device_id(client: &Client, device_name: Option<String>, device_id: Option<String>) -> Result<String> {
+    match (device_name, device_id) {
+        (Some(name), None) => {
+            let device = client.get_device_by_name(&name).await
+                .context("Failed to get device by name")?;
+            Ok(device.did)
+        }
+        (None, Some(id)) => Ok(id),
+        _ => {
+            error!("Must specify either --name or --id");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Get device IDs from any number of --name and --id, looking the names up if needed
+async fn resolve_device_ids(client: &Client, device_names: Vec<String>, mut device_ids: Vec<String>) -> Result<Vec<String>> {
+    if !device_names.is_empty() {
+        let devices = client.list_devices().await.context("Failed to list devices")?;
+        for name in device_names {
+            let device = devices.iter()
+                .find(|d| d.dev_alias.as_deref() == Some(name.as_str()))
+                .with_context(|| format!("Device with name '{}' not found", name))?;
+            device_ids.push(device.did.clone());
+        }
+    }
     
-    /// Set device mode
-    SetMode {
-        /// Device name
-        #[arg(long = "name", group = "device")]
-        device_name: Option<String>,
-        
-        /// Device ID
-        #[arg(long = "id", group = "device")]
-        device_id: Option<String>,
-        
-        /// Mode (comfort, eco, frost-protection, stop, comfort-1, comfort-2)
-        mode: String,
-    },
+    if device_ids.is_empty() {
+        error!("Must specify at least one --name or --id");
+        std::process::exit(1);
+    }
+    Ok(device_ids)
+}
+
+/// Print a device event on one line
+fn print_device_event(event: DeviceEvent) -> Result<()> {
+    match event {
+        DeviceEvent::Attributes { did, attrs } => println!("{} {}", did, serde_json::to_string(&attrs)?),
+        DeviceEvent::Connectivity { did, online } => println!("{} {}", did, if online { "online" } else { "offline" }),
+        DeviceEvent::Disconnected => warn!("Connection lost, reconnecting"),
+        DeviceEvent::Reconnected => warn!("Connection restored"),
+    }
+    Ok(())
+}
+
+/// Print the outcome of an unbinding or group update, exiting with an error if any device failed
+fn print_binding_report(report: &BindingReport, done: &str) {
+    for did in &report.success {
+        println!("{} {}", did, done);
+    }
+    for did in &report.failed {
+        println!("{} failed", did);
+    }
+    if !report.all_succeeded() {
+        std::process::exit(1);
+    }
 }
 
 #[tokio::main]
@@ -83,13 +949,26 @@ async fn main() -> Result<()> {
         .start()
         .context("Failed to initialize logger")?;
     
+    // Runs given a token do not touch the cache, so they work without a home directory
+    let needs_token_store = cli.token.is_none() || matches!(
+        cli.command,
+        Commands::Login { .. } | Commands::Logout | Commands::Listen { .. }
+            | Commands::Account { action: AccountCommands::Register { .. } }
+    );
+    let token_store = if needs_token_store { open_token_store() } else { None };
+    let mut client = build_client(&cli, token_store.clone())?;
+    
     match cli.command {
         Commands::Login { username, password } => {
             debug!("Performing login");
-            let client = Client::new().context("Failed to create client")?;
-            
             match client.login(&username, &password).await {
                 Ok(auth_response) => {
+                    if let Some(token_store) = &token_store {
+                        if let Err(e) = token_store.save(&client.token_key(&username), &auth_response) {
+                            warn!("Failed to cache token: {}", e);
+                        }
+                    }
+                    
                     // Output only the token to stdout
                     println!("{}", auth_response.token);
                 }
@@ -100,20 +979,59 @@ async fn main() -> Result<()> {
             }
         }
         
+        Commands::Logout => {
+            match (resolve_account(&cli, &client, token_store.as_deref())?, &token_store) {
+                (Some(account), Some(token_store)) => {
+                    token_store.clear(&client.token_key(&account))
+                        .context("Failed to clear cached token")?;
+                }
+                _ => {
+                    error!("No cached token to remove");
+                    std::process::exit(1);
+                }
+            }
+        }
+        
+        Commands::Account { action: AccountCommands::Register { username, password } } => {
+            let auth_response = client.register_user(&username, &password).await
+                .context("Failed to register user")?;
+            if let Some(token_store) = &token_store {
+                if let Err(e) = token_store.save(&client.token_key(&username), &auth_response) {
+                    warn!("Failed to cache token: {}", e);
+                }
+            }
+            
+            // Output only the token to stdout
+            println!("{}", auth_response.token);
+        }
+        
+        Commands::Account { action: AccountCommands::ResetPassword { email } } => {
+            client.request_password_reset(&email).await
+                .context("Failed to request password reset")?;
+            
+            println!("Password reset email sent to {}", email);
+        }
+        
         _ => {
             // All other commands require authentication
-            let mut client = Client::new().context("Failed to create client")?;
-            
-            if let Some(token) = cli.token {
+            if let Some(token) = cli.token.clone() {
                 client.set_token(token);
+            } else if let Some(account) = resolve_account(&cli, &client, token_store.as_deref())? {
+                debug!("Using cached token of {}", account);
+                client.set_account(account);
             } else {
                 error!("No authentication token provided. Use --token or login first");
                 std::process::exit(1);
             }
             
             match cli.command {
-                Commands::Devices => {
-                    let devices = client.list_devices().await.context("Failed to list devices")?;
+                Commands::Devices { show_disabled, product_key } => {
+                    let options = ListDevicesOptions {
+                        show_disabled,
+                        product_key,
+                        ..ListDevicesOptions::default()
+                    };
+                    let devices = client.list_devices_with(options).await.context("Failed to list devices")?;
                     
                     for device in devices {
                         println!("{:<30} {} {} ({})", 
@@ -126,44 +1044,35 @@ async fn main() -> Result<()> {
                 }
                 
                 Commands::Device { device_name, device_id } => {
-                    let device = match (device_name, device_id) {
-                        (Some(name), None) => {
-                            client.get_device_by_name(&name).await
-                                .context("Failed to get device by name")?
-                        }
-                        (None, Some(id)) => {
-                            client.get_device(&id).await
-                                .context("Failed to get device by ID")?
-                        }
-                        _ => {
-                            error!("Must specify either --name or --id");
-                            std::process::exit(1);
-                        }
-                    };
+                    let device = resolve_device(&client, device_name, device_id).await?;
                     
                     // Device name is not returned by this endpoint
                     if let Some(alias) = &device.dev_alias {
-                        println!("Name:    {}", alias);
+                        println!("Name:     {}", alias);
+                    }
+                    println!("ID:       {}", device.did);
+                    println!("Product:  {} ({})", device.product_name, device.product());
+                    if let Some(product_key) = &device.product_key {
+                        println!("Key:      {}", product_key);
+                    }
+                    println!("MAC:      {}", device.mac);
+                    println!("Online:   {}", if device.is_online { "Yes" } else { "No" });
+                    if let Some(remark) = device.remark.as_deref().filter(|r| !r.is_empty()) {
+                        println!("Remark:   {}", remark);
+                    }
+                    if let Some(role) = &device.role {
+                        println!("Role:     {}", role);
+                    }
+                    if let Some(version) = &device.wifi_soft_version {
+                        println!("WiFi:     {}", version);
+                    }
+                    if let Some(version) = &device.mcu_soft_version {
+                        println!("MCU:      {}", version);
                     }
-                    println!("ID:      {}", device.did);
-                    println!("Product: {}", device.product_name);
-                    println!("MAC:     {}", device.mac);
-                    println!("Online:  {}", if device.is_online { "Yes" } else { "No" });
                 }
                 
                 Commands::GetMode { device_name, device_id } => {
-                    let device_id = match (device_name, device_id) {
-                        (Some(name), None) => {
-                            let device = client.get_device_by_name(&name).await
-                                .context("Failed to get device by name")?;
-                            device.did
-                        }
-                        (None, Some(id)) => id,
-                        _ => {
-                            error!("Must specify either --device-name or --device-id");
-                            std::process::exit(1);
-                        }
-                    };
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
                     
                     let mode = client.get_device_mode(&device_id).await
                         .context("Failed to get device mode")?;
@@ -171,27 +1080,607 @@ async fn main() -> Result<()> {
                     println!("{}", mode);
                 }
                 
+                Commands::State { device_name, device_id } => {
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                    
+                    let state = client.get_device_state(&device_id).await
+                        .context("Failed to get device state")?;
+                    
+                    let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+                    if let Some(mode) = state.mode {
+                        println!("Mode:        {}", mode);
+                    }
+                    if let Some(derogation) = state.derogation_mode {
+                        match (derogation, state.derogation_time) {
+                            (DerogationMode::Boost, Some(minutes)) => println!("Derogation:  boost ({} min left)", minutes),
+                            (DerogationMode::Vacation, Some(days)) => println!("Derogation:  vacation ({} days left)", days),
+                            _ => println!("Derogation:  {}", derogation),
+                        }
+                    }
+                    if let Some(enabled) = state.program_enabled {
+                        println!("Programme:   {}", on_off(enabled));
+                    }
+                    if let Some(enabled) = state.lock_enabled {
+                        println!("Lock:        {}", on_off(enabled));
+                    }
+                    if let Some(enabled) = state.window_detection_enabled {
+                        println!("Window:      {}", on_off(enabled));
+                    }
+                    if let Some(temperature) = state.temperature {
+                        println!("Temperature: {}", temperature);
+                    }
+                    if let Some(humidity) = state.humidity {
+                        println!("Humidity:    {}", humidity);
+                    }
+                    if let Some(setpoint) = state.comfort_setpoint {
+                        println!("Comfort:     {}", setpoint);
+                    }
+                    if let Some(setpoint) = state.eco_setpoint {
+                        println!("Eco:         {}", setpoint);
+                    }
+                    if let Some(updated_at) = state.updated_at {
+                        println!("Updated at:  {}", updated_at);
+                    }
+                }
+                
                 Commands::SetMode { device_name, device_id, mode } => {
-                    let device_id = match (device_name, device_id) {
-                        (Some(name), None) => {
-                            let device = client.get_device_by_name(&name).await
-                                .context("Failed to get device by name")?;
-                            device.did
+                    let mode = DeviceMode::from_cli_str(&mode)
+                        .context("Invalid mode")?;
+                    
+                    let device = resolve_device(&client, device_name, device_id).await?;
+                    if !device.capabilities().supports_mode(mode) {
+                        error!("{} devices do not support the {} mode", device.product(), mode);
+                        std::process::exit(1);
+                    }
+                    
+                    client.set_device_mode(&device.did, mode).await
+                        .context("Failed to set device mode")?;
+                    
+                    println!("Device mode set to: {}", mode);
+                }
+                
+                Commands::Boost { device_name, device_id, minutes } => {
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                    
+                    client.set_boost(&device_id, Duration::from_secs(minutes.saturating_mul(60))).await
+                        .context("Failed to start boost")?;
+                    
+                    println!("Boost started for {} minutes", minutes);
+                }
+                
+                Commands::Vacation { device_name, device_id, days } => {
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                    
+                    client.set_vacation(&device_id, days).await
+                        .context("Failed to start vacation")?;
+                    
+                    println!("Vacation started for {} days", days);
+                }
+                
+                Commands::CancelDerogation { device_name, device_id } => {
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                    
+                    client.cancel_derogation(&device_id).await
+                        .context("Failed to cancel derogation")?;
+                    
+                    println!("Derogation cancelled");
+                }
+                
+                Commands::Lock { device_name, device_id, state } => {
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                    
+                    match state {
+                        Some(state) => {
+                            let enabled = parse_switch(&state)?;
+                            client.set_child_lock(&device_id, enabled).await
+                                .context("Failed to set child lock")?;
+                            println!("Child lock turned {}", if enabled { "on" } else { "off" });
+                        }
+                        None => {
+                            let enabled = client.get_child_lock(&device_id).await
+                                .context("Failed to get child lock")?;
+                            println!("{}", if enabled { "on" } else { "off" });
+                        }
+                    }
+                }
+                
+                Commands::WindowDetection { device_name, device_id, state } => {
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                    
+                    match state {
+                        Some(state) => {
+                            let enabled = parse_switch(&state)?;
+                            client.set_window_detection(&device_id, enabled).await
+                                .context("Failed to set window detection")?;
+                            println!("Window detection turned {}", if enabled { "on" } else { "off" });
+                        }
+                        None => {
+                            let enabled = client.get_window_detection(&device_id).await
+                                .context("Failed to get window detection")?;
+                            println!("{}", if enabled { "on" } else { "off" });
+                        }
+                    }
+                }
+                
+                Commands::Sensors { device_name, device_id } => {
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                    
+                    let sensors = client.get_sensors(&device_id).await
+                        .context("Failed to get sensors")?;
+                    
+                    if let Some(temperature) = sensors.temperature {
+                        println!("Temperature: {}", temperature);
+                    }
+                    if let Some(humidity) = sensors.humidity {
+                        println!("Humidity:    {}", humidity);
+                    }
+                    if let Some(sampled_at) = sensors.sampled_at {
+                        println!("Sampled at:  {}", sampled_at);
+                    }
+                }
+                
+                Commands::Setpoint { device_name, device_id, comfort, eco } => {
+                    let device = resolve_device(&client, device_name, device_id).await?;
+                    let capabilities = device.capabilities();
+                    if capabilities.setpoints.is_none() {
+                        error!("{} devices do not support setpoints", device.product());
+                        std::process::exit(1);
+                    }
+                    
+                    if comfort.is_none() && eco.is_none() {
+                        let setpoints = client.get_setpoints(&device.did).await
+                            .context("Failed to get setpoints")?;
+                        if let Some(comfort) = setpoints.comfort {
+                            println!("Comfort: {}", comfort);
+                        }
+                        if let Some(eco) = setpoints.eco {
+                            println!("Eco:     {}", eco);
+                        }
+                    } else {
+                        let setpoints = Setpoints {
+                            comfort: comfort.map(Temperature::from_celsius),
+                            eco: eco.map(Temperature::from_celsius),
+                        };
+                        client.set_setpoints(&device.did, setpoints).await
+                            .context("Failed to set setpoints")?;
+                        
+                        println!("Setpoints updated");
+                    }
+                }
+                
+                Commands::Schedule { action } => match action {
+                    ScheduleCommands::Show { device_name, device_id } => {
+                        let device = resolve_device(&client, device_name, device_id).await?;
+                        if device.capabilities().schedule.is_none() {
+                            error!("{} devices do not support schedules", device.product());
+                            std::process::exit(1);
+                        }
+                        
+                        let state = client.get_device_state(&device.did).await
+                            .context("Failed to get device state")?;
+                        let schedule = WeeklySchedule::from_attributes(&state.attributes)
+                            .context("Failed to read schedule")?;
+                        
+                        if let Some(enabled) = state.program_enabled {
+                            println!("Programme: {}", if enabled { "on" } else { "off" });
+                        }
+                        print_schedule_grid(&schedule);
+                    }
+                    
+                    ScheduleCommands::Set { device_name, device_id, file, format, enable } => {
+                        let format = ScheduleFileFormat::resolve(format.as_deref(), Some(&file))?;
+                        let content = if file.as_os_str() == "-" {
+                            std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?
+                        } else {
+                            std::fs::read_to_string(&file)
+                                .with_context(|| format!("Failed to read {}", file.display()))?
+                        };
+                        let schedule = format.parse(&content)?;
+                        
+                        let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                        client.set_schedule(&device_id, &schedule).await
+                            .context("Failed to set schedule")?;
+                        if enable {
+                            client.set_schedule_enabled(&device_id, true).await
+                                .context("Failed to enable schedule")?;
+                        }
+                        
+                        println!("Schedule updated");
+                    }
+                    
+                    ScheduleCommands::Export { device_name, device_id, format, output } => {
+                        let format = ScheduleFileFormat::resolve(format.as_deref(), output.as_deref())?;
+                        let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                        
+                        let schedule = client.get_schedule(&device_id).await
+                            .context("Failed to get schedule")?;
+                        let content = format.format(&schedule)?;
+                        
+                        match output {
+                            Some(path) => std::fs::write(&path, content)
+                                .with_context(|| format!("Failed to write {}", path.display()))?,
+                            None => print!("{}", content),
+                        }
+                    }
+                },
+                
+                Commands::Account { action } => match action {
+                    AccountCommands::Info => {
+                        let user = client.get_user_info().await
+                            .context("Failed to get user info")?;
+                        
+                        println!("UID:      {}", user.uid);
+                        if let Some(username) = &user.username {
+                            println!("Username: {}", username);
+                        }
+                        if let Some(email) = user.email.as_deref().filter(|e| !e.is_empty()) {
+                            println!("Email:    {}", email);
+                        }
+                        if let Some(phone) = user.phone.as_deref().filter(|p| !p.is_empty()) {
+                            println!("Phone:    {}", phone);
+                        }
+                        if let Some(name) = user.name.as_deref().filter(|n| !n.is_empty()) {
+                            println!("Name:     {}", name);
+                        }
+                        if let Some(lang) = &user.lang {
+                            println!("Language: {}", lang);
+                        }
+                        if let Some(remark) = user.remark.as_deref().filter(|r| !r.is_empty()) {
+                            println!("Remark:   {}", remark);
+                        }
+                    }
+                    
+                    AccountCommands::Update { email, phone, name, lang, remark } => {
+                        let update = UserUpdate { email, phone, name, lang, remark };
+                        if update.is_empty() {
+                            error!("Nothing to update");
+                            std::process::exit(1);
+                        }
+                        
+                        client.update_user(&update).await
+                            .context("Failed to update user")?;
+                        
+                        println!("Account updated");
+                    }
+                    
+                    AccountCommands::ChangePassword { old, new } => {
+                        client.change_password(&old, &new).await
+                            .context("Failed to change password")?;
+                        
+                        println!("Password changed");
+                    }
+                    
+                    AccountCommands::Register { .. } | AccountCommands::ResetPassword { .. } => unreachable!(),
+                },
+                
+                Commands::Bind { device_id, passcode, mac, product_key, product_secret, alias, remark } => {
+                    match (device_id, passcode, mac, product_key, product_secret) {
+                        (Some(device_id), Some(passcode), None, _, _) => {
+                            client.bind_device(&device_id, &passcode, alias.as_deref(), remark.as_deref()).await
+                                .context("Failed to bind device")?;
+                            println!("Device {} bound", device_id);
+                        }
+                        (None, _, Some(mac), Some(product_key), Some(product_secret)) => {
+                            client.set_product_secret(product_key.clone(), product_secret);
+                            let device = client.bind_by_mac(&product_key, &mac).await
+                                .context("Failed to bind device")?;
+                            println!("Device {} bound", device.did);
                         }
-                        (None, Some(id)) => id,
                         _ => {
-                            error!("Must specify either --device-name or --device-id");
+                            error!("Must specify either --id and --passcode, or --mac, --product-key and --product-secret");
                             std::process::exit(1);
                         }
-                    };
+                    }
+                }
+                
+                Commands::Unbind { device_names, device_ids } => {
+                    let device_ids = resolve_device_ids(&client, device_names, device_ids).await?;
+                    let device_ids: Vec<&str> = device_ids.iter().map(String::as_str).collect();
+                    let report = client.unbind_devices(&device_ids).await
+                        .context("Failed to unbind devices")?;
                     
-                    let mode = DeviceMode::from_cli_str(&mode)
-                        .context("Invalid mode")?;
+                    print_binding_report(&report, "unbound");
+                }
+                
+                Commands::Rename { device_name, device_id, alias, remark } => {
+                    let device_id = resolve_device_id(&client, device_name, device_id).await?;
                     
-                    client.set_device_mode(&device_id, mode).await
-                        .context("Failed to set device mode")?;
+                    client.rename_device(&device_id, &alias).await
+                        .context("Failed to rename device")?;
+                    if let Some(remark) = remark {
+                        client.set_device_remark(&device_id, &remark).await
+                            .context("Failed to set device remark")?;
+                    }
                     
-                    println!("Device mode set to: {}", mode);
+                    println!("Device renamed to: {}", alias);
+                }
+                
+                Commands::Group { action } => match action {
+                    GroupCommands::List => {
+                        let groups = client.list_groups().await
+                            .context("Failed to list groups")?;
+                        
+                        for group in groups {
+                            println!("{:<30} {}", group.name, group.id);
+                        }
+                    }
+                    
+                    GroupCommands::Create { name, product_key } => {
+                        let group_id = client.create_group(&name, product_key.as_deref()).await
+                            .context("Failed to create group")?;
+                        
+                        println!("{}", group_id);
+                    }
+                    
+                    GroupCommands::Rename { group_id, name } => {
+                        client.rename_group(&group_id, &name).await
+                            .context("Failed to rename group")?;
+                        
+                        println!("Group renamed to: {}", name);
+                    }
+                    
+                    GroupCommands::Delete { group_id } => {
+                        client.delete_group(&group_id).await
+                            .context("Failed to delete group")?;
+                        
+                        println!("Group deleted");
+                    }
+                    
+                    GroupCommands::Devices { group_id } => {
+                        let devices = client.list_group_devices(&group_id).await
+                            .context("Failed to list group devices")?;
+                        
+                        for device in devices {
+                            println!("{:<30} {}", device.dev_alias.as_deref().unwrap_or("(no name)"), device.did);
+                        }
+                    }
+                    
+                    GroupCommands::Add { group_id, device_names, device_ids } => {
+                        let device_ids = resolve_device_ids(&client, device_names, device_ids).await?;
+                        let device_ids: Vec<&str> = device_ids.iter().map(String::as_str).collect();
+                        
+                        let report = client.add_group_devices(&group_id, &device_ids).await
+                            .context("Failed to add devices to group")?;
+                        
+                        print_binding_report(&report, "added");
+                    }
+                    
+                    GroupCommands::Remove { group_id, device_names, device_ids } => {
+                        let device_ids = resolve_device_ids(&client, device_names, device_ids).await?;
+                        let device_ids: Vec<&str> = device_ids.iter().map(String::as_str).collect();
+                        
+                        let report = client.remove_group_devices(&group_id, &device_ids).await
+                            .context("Failed to remove devices from group")?;
+                        
+                        print_binding_report(&report, "removed");
+                    }
+                    
+                    GroupCommands::SetMode { group_id, mode } => {
+                        let mode = DeviceMode::from_cli_str(&mode)
+                            .context("Invalid mode")?;
+                        
+                        let outcomes = client.set_group_mode(&group_id, mode).await
+                            .context("Failed to set group mode")?;
+                        
+                        let mut failed = false;
+                        for outcome in outcomes {
+                            match outcome.result {
+                                Ok(()) => println!("{} set to {}", outcome.did, mode),
+                                Err(e) => {
+                                    println!("{} failed: {}", outcome.did, e);
+                                    failed = true;
+                                }
+                            }
+                        }
+                        if failed {
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                
+                Commands::Share { action } => match action {
+                    ShareCommands::Create { device_name, device_id, uid, username, email, phone, qr_minutes } => {
+                        let target = match (uid, username, email, phone, qr_minutes) {
+                            (Some(uid), ..) => ShareTarget::Uid(uid),
+                            (_, Some(username), ..) => ShareTarget::Username(username),
+                            (_, _, Some(email), ..) => ShareTarget::Email(email),
+                            (_, _, _, Some(phone), _) => ShareTarget::Phone(phone),
+                            (_, _, _, _, Some(duration_minutes)) => ShareTarget::QrCode { duration_minutes },
+                            _ => {
+                                error!("Must specify one of --uid, --username, --email, --phone or --qr-minutes");
+                                std::process::exit(1);
+                            }
+                        };
+                        let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                        
+                        let invitation = client.create_sharing(&device_id, &target).await
+                            .context("Failed to share device")?;
+                        
+                        match invitation.qr_content {
+                            Some(qr_content) => println!("{}", qr_content),
+                            None => println!("{}", invitation.id),
+                        }
+                    }
+                    
+                    ShareCommands::List { received } => {
+                        let role = if received { SharingRole::Guest } else { SharingRole::Owner };
+                        let sharings = client.list_sharings(role).await
+                            .context("Failed to list sharings")?;
+                        
+                        for sharing in sharings {
+                            println!("{:<10} {:<30} {} {} {}",
+                                sharing.id,
+                                sharing.dev_alias.as_deref().unwrap_or("(no name)"),
+                                sharing.did,
+                                sharing.user_name.as_deref().or(sharing.uid.as_deref()).unwrap_or("(QR code)"),
+                                sharing.status
+                            );
+                        }
+                    }
+                    
+                    ShareCommands::Accept { sharing_id, code } => {
+                        match (sharing_id, code) {
+                            (Some(sharing_id), None) => client.accept_sharing(&sharing_id).await,
+                            (None, Some(code)) => client.accept_sharing_code(&code).await,
+                            _ => {
+                                error!("Must specify either a sharing ID or --code");
+                                std::process::exit(1);
+                            }
+                        }.context("Failed to accept sharing")?;
+                        
+                        println!("Sharing accepted");
+                    }
+                    
+                    ShareCommands::Refuse { sharing_id } => {
+                        client.refuse_sharing(&sharing_id).await
+                            .context("Failed to refuse sharing")?;
+                        
+                        println!("Sharing refused");
+                    }
+                    
+                    ShareCommands::Revoke { sharing_id } => {
+                        client.revoke_sharing(&sharing_id).await
+                            .context("Failed to revoke sharing")?;
+                        
+                        println!("Sharing revoked");
+                    }
+                    
+                    ShareCommands::Guests { device_name, device_id } => {
+                        let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                        
+                        let guests = client.list_guests(&device_id).await
+                            .context("Failed to list guests")?;
+                        
+                        for guest in guests {
+                            let contact = guest.username.as_deref()
+                                .or(guest.email.as_deref())
+                                .or(guest.phone.as_deref())
+                                .unwrap_or("");
+                            println!("{} {}", guest.uid, contact);
+                        }
+                    }
+                    
+                    ShareCommands::RemoveGuest { device_name, device_id, uid } => {
+                        let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                        
+                        client.remove_guest(&device_id, &uid).await
+                            .context("Failed to remove guest")?;
+                        
+                        println!("Guest removed");
+                    }
+                },
+                
+                Commands::Timer { action } => match action {
+                    TimerCommands::List { device_name, device_id } => {
+                        let device_id = match (device_name, device_id) {
+                            (None, None) => None,
+                            (device_name, device_id) => Some(resolve_device_id(&client, device_name, device_id).await?),
+                        };
+                        
+                        let tasks = client.list_scheduled_tasks().await
+                            .context("Failed to list scheduler tasks")?;
+                        
+                        for task in tasks {
+                            if device_id.as_deref().is_some_and(|did| !task.targets_device(did)) {
+                                continue;
+                            }
+                            
+                            println!("{}  {} UTC, {}{}",
+                                task.id,
+                                task.time,
+                                task.repeat,
+                                if task.enabled { "" } else { " (disabled)" }
+                            );
+                            for target in &task.targets {
+                                println!("    {} {}", target.did, serde_json::to_string(&target.attrs)?);
+                            }
+                            if let Some(remark) = &task.remark {
+                                println!("    {}", remark);
+                            }
+                        }
+                    }
+                    
+                    TimerCommands::Create { device_name, device_id, task } => {
+                        let task = task.to_task()?;
+                        let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                        
+                        let task_id = client.create_scheduled_task(&device_id, &task).await
+                            .context("Failed to create scheduler task")?;
+                        
+                        println!("{}", task_id);
+                    }
+                    
+                    TimerCommands::Update { task_id, device_name, device_id, task } => {
+                        let task = task.to_task()?;
+                        let device_id = resolve_device_id(&client, device_name, device_id).await?;
+                        
+                        client.update_scheduled_task(&task_id, &device_id, &task).await
+                            .context("Failed to update scheduler task")?;
+                        
+                        println!("Scheduler task updated");
+                    }
+                    
+                    TimerCommands::Delete { task_id } => {
+                        client.delete_scheduled_task(&task_id).await
+                            .context("Failed to delete scheduler task")?;
+                        
+                        println!("Scheduler task deleted");
+                    }
+                },
+                
+                Commands::Listen { device_names, device_ids, poll, uid } => {
+                    // The websocket login needs the user id, which a bare token lacks
+                    if let Some(token) = &cli.token {
+                        match uid {
+                            Some(uid) => client.set_session(Session {
+                                token: token.clone(),
+                                uid: Some(uid),
+                                expire_at: None,
+                            }),
+                            None if poll.is_none() => {
+                                error!("Listening with --token needs --uid, or --poll to poll instead");
+                                std::process::exit(1);
+                            }
+                            None => {}
+                        }
+                    }
+                    
+                    let device_ids = if !device_names.is_empty() || !device_ids.is_empty() {
+                        resolve_device_ids(&client, device_names, device_ids).await?
+                    } else if poll.is_some() {
+                        client.list_devices().await
+                            .context("Failed to list devices")?
+                            .into_iter()
+                            .map(|device| device.did)
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                    let device_ids: Vec<&str> = device_ids.iter().map(String::as_str).collect();
+                    
+                    if let Some(seconds) = poll {
+                        let events = client.watch(&device_ids, Duration::from_secs(seconds));
+                        futures::pin_mut!(events);
+                        
+                        while let Some(event) = events.next().await {
+                            match event {
+                                Ok(event) => print_device_event(event)?,
+                                Err(e) => warn!("Polling failed: {}", e),
+                            }
+                        }
+                    } else {
+                        // Shared with the session so reconnections can renew the token
+                        let client = Arc::new(client);
+                        let mut session = client.websocket_session(&device_ids).await
+                            .context("Failed to open websocket session")?;
+                        
+                        while let Some(event) = session.next().await {
+                            print_device_event(event)?;
+                        }
+                        
+                        error!("Websocket session ended");
+                        std::process::exit(1);
+                    }
                 }
                 
                 _ => unreachable!(),
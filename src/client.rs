@@ -1,39 +1,340 @@
+use crate::batch::BatchReport;
 use crate::error::HeatzyError;
 use crate::models::*;
-use log::{debug, info, trace};
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use crate::retry::RetryPolicy;
+use crate::schedule::WeeklySchedule;
+use crate::group::{ControlOutcome, CreatedGroupResponse, DeviceGroup, GroupDevice, GroupDevicesRequest, GroupRequest};
+use crate::sharing::{Guest, GuestsResponse, ShareTarget, Sharing, SharingInvitation, SharingRequest, SharingRole, SharingsResponse};
+use crate::scheduler::{CreatedResponse, NewScheduledTask, ScheduledTask, SchedulerResponse, TaskAction};
+use crate::token_store::{TokenKey, TokenStore};
+use crate::watch::Watcher;
+use crate::websocket::{DeviceEvent, WebSocketSession};
+use futures::{Future, Stream, StreamExt, TryStreamExt};
+use log::{debug, info, trace, warn};
+use reqwest::header::HeaderValue;
+use reqwest::Method;
+use crate::product::Product;
+use crate::state::{encode_temperature, DerogationMode, DeviceState, Sensors, Setpoints, Temperature};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::RwLock;
 
-const BASE_URL: &str = "https://euapi.gizwits.com/app";
 const APP_ID: &str = "c70a66ff039d41b4a220e198b0fcc8b3";
 const APP_ID_HEADER: &str = "X-Gizwits-Application-Id";
 const USER_TOKEN_HEADER: &str = "X-Gizwits-User-token";
+const TIMESTAMP_HEADER: &str = "X-Gizwits-Timestamp";
+const SIGNATURE_HEADER: &str = "X-Gizwits-Signature";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RENEW_MARGIN: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_MAX_CONCURRENCY: usize = 8;
+
+/// Gizwits cloud region hosting the account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Europe,
+    UnitedStates,
+    China,
+}
+
+impl Region {
+    /// Base URL of the REST API for this region
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            Region::Europe => "https://euapi.gizwits.com/app",
+            Region::UnitedStates => "https://usapi.gizwits.com/app",
+            Region::China => "https://api.gizwits.com/app",
+        }
+    }
+
+    /// URL of the real-time websocket for this region
+    pub fn websocket_url(&self) -> &'static str {
+        match self {
+            Region::Europe => "wss://eum2m.gizwits.com:8880/ws/app/v1",
+            Region::UnitedStates => "wss://usm2m.gizwits.com:8880/ws/app/v1",
+            Region::China => "wss://m2m.gizwits.com:8880/ws/app/v1",
+        }
+    }
+
+    /// Convert from CLI string value
+    pub fn from_cli_str(value: &str) -> Result<Self, HeatzyError> {
+        match value.to_lowercase().as_str() {
+            "eu" | "europe" => Ok(Region::Europe),
+            "us" | "usa" => Ok(Region::UnitedStates),
+            "cn" | "china" => Ok(Region::China),
+            _ => Err(HeatzyError::Config(format!("Invalid region: {}. Valid regions are: eu, us, cn", value))),
+        }
+    }
+
+    /// Get CLI-friendly string representation
+    pub fn to_cli_str(&self) -> &'static str {
+        match self {
+            Region::Europe => "eu",
+            Region::UnitedStates => "us",
+            Region::China => "cn",
+        }
+    }
+}
+
+/// Builder for [`Client`]
+///
+/// All settings are optional; the defaults match [`Client::new`].
+pub struct ClientBuilder {
+    region: Region,
+    base_url: Option<String>,
+    websocket_url: Option<String>,
+    app_id: String,
+    timeout: Duration,
+    user_agent: Option<String>,
+    proxy: Option<reqwest::Proxy>,
+    root_certificates: Vec<reqwest::Certificate>,
+    http_client: Option<reqwest::Client>,
+    credentials: Option<LoginCredentials>,
+    renew_margin: Duration,
+    token_store: Option<Arc<dyn TokenStore>>,
+    account: Option<String>,
+    retry_policy: RetryPolicy,
+    product_secrets: HashMap<String, String>,
+    max_concurrency: usize,
+    check_online: bool,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            region: Region::default(),
+            base_url: None,
+            websocket_url: None,
+            app_id: APP_ID.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            user_agent: None,
+            proxy: None,
+            root_certificates: Vec::new(),
+            http_client: None,
+            credentials: None,
+            renew_margin: DEFAULT_RENEW_MARGIN,
+            token_store: None,
+            account: None,
+            retry_policy: RetryPolicy::default(),
+            product_secrets: HashMap::new(),
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            check_online: false,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Create a builder with the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select the Gizwits region (defaults to Europe)
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// Override the API base URL, e.g. for a proxy or a mock server
+    ///
+    /// Takes precedence over [`ClientBuilder::region`].
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// Override the real-time websocket URL
+    ///
+    /// Takes precedence over [`ClientBuilder::region`].
+    pub fn websocket_url(mut self, websocket_url: impl Into<String>) -> Self {
+        self.websocket_url = Some(websocket_url.into());
+        self
+    }
+
+    /// Override the Gizwits application id
+    pub fn app_id(mut self, app_id: impl Into<String>) -> Self {
+        self.app_id = app_id.into();
+        self
+    }
+
+    /// Set the request timeout (defaults to 30 seconds)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the User-Agent header sent with each request
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Route requests through a proxy
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Trust an additional root certificate
+    pub fn add_root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Use an existing HTTP client
+    ///
+    /// The timeout, user agent, proxy and root certificate settings are ignored
+    /// in that case and must be configured on the injected client instead.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Log in lazily with these credentials and renew the token when needed
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        let username = username.into();
+        self.account = Some(username.clone());
+        self.credentials = Some(LoginCredentials {
+            username,
+            password: password.into(),
+        });
+        self
+    }
+    
+    /// Cache tokens in this store and reuse them while they are valid
+    pub fn token_store(mut self, token_store: Arc<dyn TokenStore>) -> Self {
+        self.token_store = Some(token_store);
+        self
+    }
+    
+    /// Account whose cached token is used when no credentials are given
+    pub fn account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+    
+    /// How long before expiry the token is proactively renewed (defaults to 10 minutes)
+    pub fn renew_margin(mut self, renew_margin: Duration) -> Self {
+        self.renew_margin = renew_margin;
+        self
+    }
+
+    /// Set the policy used to retry failed requests
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Number of requests sent at once by batch operations (defaults to 8)
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// List the devices before batch operations to skip offline ones without
+    /// sending them requests (off by default)
+    pub fn check_online(mut self, check_online: bool) -> Self {
+        self.check_online = check_online;
+        self
+    }
+
+    /// Register the secret of a product, needed to bind its devices by MAC address
+    pub fn product_secret(mut self, product_key: impl Into<String>, product_secret: impl Into<String>) -> Self {
+        self.product_secrets.insert(product_key.into(), product_secret.into());
+        self
+    }
+
+    /// Build the client
+    pub fn build(self) -> Result<Client, HeatzyError> {
+        let app_id = HeaderValue::from_str(&self.app_id)
+            .map_err(|_| HeatzyError::Config(format!("Invalid application id: {}", self.app_id)))?;
+
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => {
+                let mut builder = reqwest::Client::builder()
+                    .use_rustls_tls()
+                    .timeout(self.timeout);
+
+                if let Some(user_agent) = &self.user_agent {
+                    builder = builder.user_agent(user_agent);
+                }
+                if let Some(proxy) = self.proxy {
+                    builder = builder.proxy(proxy);
+                }
+                for certificate in self.root_certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+
+                builder.build()?
+            }
+        };
+
+        // Tokens are only valid on the cluster that issued them
+        let token_region = match &self.base_url {
+            Some(base_url) => base_url.clone(),
+            None => self.region.to_cli_str().to_string(),
+        };
+        let base_url = self.base_url
+            .unwrap_or_else(|| self.region.base_url().to_string())
+            .trim_end_matches('/')
+            .to_string();
+        debug!("Using API base URL: {}", base_url);
+        let websocket_url = self.websocket_url
+            .unwrap_or_else(|| self.region.websocket_url().to_string());
+
+        Ok(Client {
+            http_client,
+            base_url,
+            websocket_url,
+            app_id,
+            credentials: Mutex::new(self.credentials),
+            renew_margin: self.renew_margin,
+            session: RwLock::new(None),
+            token_store: self.token_store,
+            token_region,
+            account: self.account,
+            retry_policy: self.retry_policy,
+            product_secrets: self.product_secrets,
+            max_concurrency: self.max_concurrency,
+            check_online: self.check_online,
+            products: Mutex::new(HashMap::new()),
+        })
+    }
+}
 
 /// Heatzy API client
 pub struct Client {
     http_client: reqwest::Client,
     base_url: String,
-    token: Option<String>,
+    websocket_url: String,
+    app_id: HeaderValue,
+    /// Behind a lock so a password change can update them
+    credentials: Mutex<Option<LoginCredentials>>,
+    renew_margin: Duration,
+    session: RwLock<Option<Session>>,
+    token_store: Option<Arc<dyn TokenStore>>,
+    token_region: String,
+    account: Option<String>,
+    retry_policy: RetryPolicy,
+    /// Secret of each product, keyed by product key
+    product_secrets: HashMap<String, String>,
+    max_concurrency: usize,
+    check_online: bool,
+    /// Product of each device seen so far, keyed by device id
+    products: Mutex<HashMap<String, Product>>,
 }
 
 impl Client {
-    /// Create a new Heatzy client
+    /// Create a new Heatzy client with the default settings
     pub fn new() -> Result<Self, HeatzyError> {
-        let mut headers = HeaderMap::new();
-        headers.insert(APP_ID_HEADER, HeaderValue::from_static(APP_ID));
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        
-        let http_client = reqwest::Client::builder()
-            .use_rustls_tls()
-            .default_headers(headers)
-            .timeout(Duration::from_secs(30))
-            .build()?;
-        
-        Ok(Self {
-            http_client,
-            base_url: BASE_URL.to_string(),
-            token: None,
-        })
+        Self::builder().build()
+    }
+    
+    /// Create a builder to configure a client
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
     }
     
     /// Login to the API and return the authentication response
@@ -47,16 +348,18 @@ impl Client {
         };
         
         debug!("Sending login request");
-        let response = self.http_client
-            .post(&url)
+        let response = self.request(Method::POST, &url)
             .json(&credentials)
             .send()
             .await?;
         
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(HeatzyError::Auth(format!("Login failed with status {}: {}", status, error_text)));
+            return Err(match HeatzyError::from_response(response).await {
+                HeatzyError::Http { status, body } => {
+                    HeatzyError::Auth(format!("Login failed with status {}: {}", status, body))
+                }
+                e => e,
+            });
         }
         
         let auth_response: AuthResponse = response.json().await?;
@@ -66,37 +369,229 @@ impl Client {
         Ok(auth_response)
     }
     
+    /// Create an account and return its authentication response
+    pub async fn register_user(&self, username: &str, password: &str) -> Result<AuthResponse, HeatzyError> {
+        info!("Registering user {}", username);
+        
+        let url = format!("{}/users", self.base_url);
+        let credentials = LoginCredentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+        
+        let response = self.request(Method::POST, &url)
+            .json(&credentials)
+            .send()
+            .await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let auth_response: AuthResponse = response.json().await?;
+        info!("Successfully registered user {}", auth_response.uid);
+        Ok(auth_response)
+    }
+    
+    /// Send a password reset email
+    pub async fn request_password_reset(&self, email: &str) -> Result<(), HeatzyError> {
+        info!("Requesting password reset for {}", email);
+        
+        let url = format!("{}/reset_password", self.base_url);
+        let response = self.request(Method::POST, &url)
+            .json(&PasswordReset { email })
+            .send()
+            .await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the details of the logged in account
+    pub async fn get_user_info(&self) -> Result<UserInfo, HeatzyError> {
+        info!("Getting user info");
+        
+        let url = format!("{}/users", self.base_url);
+        let response = self.authenticated_get(&url).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        Ok(response.json().await?)
+    }
+    
+    /// Change the details of the logged in account
+    pub async fn update_user(&self, update: &UserUpdate) -> Result<(), HeatzyError> {
+        info!("Updating user info");
+        
+        if update.is_empty() {
+            return Ok(());
+        }
+        
+        let url = format!("{}/users", self.base_url);
+        let response = self.authenticated_put(&url, update).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        info!("Successfully updated user info");
+        Ok(())
+    }
+    
+    /// Change the password of the logged in account
+    ///
+    /// Credentials given to the client are updated and a new token is fetched
+    /// with them, so the session can still be renewed afterwards. Without
+    /// credentials the session is dropped, as its token may be revoked.
+    pub async fn change_password(&self, old_password: &str, new_password: &str) -> Result<(), HeatzyError> {
+        info!("Changing password");
+        
+        let url = format!("{}/users", self.base_url);
+        let body = PasswordChange { old_pwd: old_password, new_pwd: new_password };
+        let response = self.authenticated_put(&url, &body).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        info!("Successfully changed password");
+        
+        let username = self.credentials.lock().unwrap().as_mut().map(|credentials| {
+            credentials.password = new_password.to_string();
+            credentials.username.clone()
+        });
+        
+        // Tokens issued for the old password may be revoked
+        let Some(username) = username else {
+            warn!("No credentials to log in with the new password, dropping the session");
+            self.clear_cached_session();
+            *self.session.write().await = None;
+            return Ok(());
+        };
+        match self.login(&username, new_password).await {
+            Ok(auth_response) => {
+                self.cache_session(&auth_response);
+                *self.session.write().await = Some(auth_response.into());
+            }
+            Err(e) => {
+                warn!("Failed to log in with the new password: {}", e);
+                self.clear_cached_session();
+                *self.session.write().await = None;
+            }
+        }
+        Ok(())
+    }
+    
     /// Connect to the API with username and password (login and set token)
+    ///
+    /// The credentials are kept so the token can be renewed when it expires.
+    /// A valid token cached in the token store is reused instead of logging in.
     pub async fn connect(&mut self, username: &str, password: &str) -> Result<(), HeatzyError> {
+        *self.credentials.get_mut().unwrap() = Some(LoginCredentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+        self.account = Some(username.to_string());
+        
+        if let Some(session) = self.cached_session() {
+            if !session.expires_within(self.renew_margin) {
+                info!("Reusing cached token");
+                *self.session.get_mut() = Some(session);
+                return Ok(());
+            }
+        }
+        
         let auth_response = self.login(username, password).await?;
-        self.set_token(auth_response.token);
+        self.cache_session(&auth_response);
+        *self.session.get_mut() = Some(auth_response.into());
         Ok(())
     }
     
     /// Set the authentication token manually
+    ///
+    /// Without credentials such a token cannot be renewed.
     pub fn set_token(&mut self, token: String) {
         debug!("Setting token manually");
-        self.token = Some(token);
+        *self.session.get_mut() = Some(Session {
+            token,
+            uid: None,
+            expire_at: None,
+        });
+    }
+    
+    /// Set a session obtained elsewhere, e.g. a token with its user id
+    ///
+    /// Without credentials its token cannot be renewed.
+    pub fn set_session(&mut self, session: Session) {
+        debug!("Setting session manually");
+        *self.session.get_mut() = Some(session);
+    }
+    
+    /// Set the account whose cached token should be used
+    pub fn set_account(&mut self, account: String) {
+        self.account = Some(account);
+    }
+    
+    /// Register the secret of a product, needed to bind its devices by MAC address
+    pub fn set_product_secret(&mut self, product_key: String, product_secret: String) {
+        self.product_secrets.insert(product_key, product_secret);
+    }
+    
+    /// Get the current session, if any
+    pub async fn session(&self) -> Option<Session> {
+        self.session.read().await.clone()
+    }
+    
+    /// Key under which the token of an account is cached
+    pub fn token_key(&self, account: &str) -> TokenKey {
+        TokenKey {
+            account: account.to_string(),
+            region: self.token_region.clone(),
+        }
+    }
+    
+    /// Open a real-time session on the Gizwits websocket
+    ///
+    /// Subscribes to the given devices, or to every bound device when empty.
+    /// Requires a session obtained by logging in, as the user id is needed.
+    /// Reconnections fetch a token from the client, renewing it when needed.
+    pub async fn websocket_session(self: &Arc<Self>, device_ids: &[&str]) -> Result<WebSocketSession, HeatzyError> {
+        let token = self.access_token().await?;
+        let uid = self.session().await
+            .and_then(|session| session.uid)
+            .ok_or_else(|| HeatzyError::Auth("The user id is unknown, log in to open a websocket session".to_string()))?;
+        let app_id = self.app_id.to_str()
+            .map_err(|_| HeatzyError::Config("Invalid application id".to_string()))?;
+        
+        let client = Arc::clone(self);
+        WebSocketSession::builder(app_id, uid, token)
+            .url(self.websocket_url.clone())
+            .token_provider(move || {
+                let client = Arc::clone(&client);
+                async move { client.access_token().await }
+            })
+            .devices(device_ids.iter().copied())
+            .connect()
+            .await
     }
     
     /// List all devices
     pub async fn list_devices(&self) -> Result<Vec<Device>, HeatzyError> {
-        self.ensure_authenticated()?;
+        self.list_devices_with(ListDevicesOptions::default()).await
+    }
+    
+    /// List all devices matching the options, fetching every page
+    pub async fn list_devices_with(&self, options: ListDevicesOptions) -> Result<Vec<Device>, HeatzyError> {
         info!("Listing devices");
+        let devices: Vec<Device> = self.list_devices_stream(options).try_collect().await?;
+        info!("Found {} devices", devices.len());
         
-        let url = format!("{}/bindings?limit=100&skip=0", self.base_url);
-        let response = self.authenticated_get(&url).await?;
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(HeatzyError::Api(format!("Failed to list devices with status {}: {}", status, error_text)));
-        }
-        
-        let devices_response: DevicesResponse = response.json().await?;
-        info!("Found {} devices", devices_response.devices.len());
-        
-        for device in &devices_response.devices {
+        for device in &devices {
             debug!("Device: {} ({}) - Online: {}", 
                 device.dev_alias.as_deref().unwrap_or("(no name)"), 
                 device.did, 
@@ -104,6 +599,100 @@ impl Client {
             );
         }
         
+        Ok(devices)
+    }
+    
+    /// Stream the devices matching the options, fetching pages as they are consumed
+    ///
+    /// Paging stops on an empty page rather than a short one, as the server may
+    /// return fewer devices than requested, and when a page only repeats devices
+    /// already seen.
+    pub fn list_devices_stream(&self, options: ListDevicesOptions) -> impl Stream<Item = Result<Device, HeatzyError>> + '_ {
+        let page_size = options.page_size.max(1);
+        let product_key = options.product_key.clone();
+        
+        futures::stream::try_unfold(Some((0u32, HashSet::new())), move |state| {
+            let show_disabled = options.show_disabled;
+            async move {
+                let Some((skip, mut seen)) = state else {
+                    return Ok(None);
+                };
+                
+                let page = self.list_devices_page(show_disabled, page_size, skip).await?;
+                let fetched = page.len() as u32;
+                let page: Vec<Device> = page.into_iter()
+                    .filter(|device| seen.insert(device.did.clone()))
+                    .collect();
+                
+                let next = if fetched == 0 {
+                    None
+                } else if page.is_empty() {
+                    warn!("The server returned the same devices again at offset {}, stopping", skip);
+                    None
+                } else {
+                    Some((skip + fetched, seen))
+                };
+                Ok::<_, HeatzyError>(Some((page, next)))
+            }
+        })
+        .map_ok(|page| {
+            self.remember_products(&page);
+            futures::stream::iter(page.into_iter().map(Ok))
+        })
+        .try_flatten()
+        .try_filter(move |device| {
+            let keep = product_key.is_none() || device.product_key == product_key;
+            futures::future::ready(keep)
+        })
+        .boxed()
+    }
+    
+    /// Helper fetching every page of a listing, given the page starting at an offset
+    ///
+    /// As with [`Client::list_devices_stream`], paging stops on an empty page
+    /// or on a page only repeating items already seen.
+    async fn fetch_all_pages<T, F, Fut, I>(&self, fetch_page: F, id: I) -> Result<Vec<T>, HeatzyError>
+    where
+        F: Fn(usize) -> Fut,
+        Fut: Future<Output = Result<Vec<T>, HeatzyError>>,
+        I: Fn(&T) -> String,
+    {
+        let mut items = Vec::new();
+        let mut seen = HashSet::new();
+        let mut skip = 0;
+        loop {
+            let page = fetch_page(skip).await?;
+            let fetched = page.len();
+            if fetched == 0 {
+                break;
+            }
+            
+            let before = items.len();
+            items.extend(page.into_iter().filter(|item| seen.insert(id(item))));
+            if items.len() == before {
+                warn!("The server returned the same items again at offset {}, stopping", skip);
+                break;
+            }
+            skip += fetched;
+        }
+        Ok(items)
+    }
+    
+    /// Helper fetching a single page of bound devices
+    async fn list_devices_page(&self, show_disabled: bool, limit: u32, skip: u32) -> Result<Vec<Device>, HeatzyError> {
+        debug!("Fetching devices {} to {}", skip, skip + limit);
+        
+        let url = format!(
+            "{}/bindings?limit={}&skip={}&show_disabled={}",
+            self.base_url, limit, skip, u8::from(show_disabled)
+        );
+        let response = self.authenticated_get(&url).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let devices_response: DevicesResponse = response.json().await?;
         Ok(devices_response.devices)
     }
     
@@ -120,7 +709,6 @@ impl Client {
     
     /// Get device information by ID
     pub async fn get_device(&self, device_id: &str) -> Result<Device, HeatzyError> {
-        self.ensure_authenticated()?;
         info!("Getting device info for: {}", device_id);
         
         let url = format!("{}/devices/{}", self.base_url, device_id);
@@ -131,112 +719,1241 @@ impl Client {
         }
         
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(HeatzyError::Api(format!("Failed to get device with status {}: {}", status, error_text)));
+            return Err(HeatzyError::from_response(response).await);
         }
         
         let device: Device = response.json().await?;
+        self.remember_products(std::slice::from_ref(&device));
         Ok(device)
     }
     
-    /// Get the current mode of a device
-    pub async fn get_device_mode(&self, device_id: &str) -> Result<DeviceMode, HeatzyError> {
-        self.ensure_authenticated()?;
-        info!("Getting mode for device: {}", device_id);
+    /// Get the product generation of a device, fetching the device if it was not seen yet
+    pub async fn get_device_product(&self, device_id: &str) -> Result<Product, HeatzyError> {
+        if let Some(product) = self.products.lock().unwrap().get(device_id) {
+            return Ok(*product);
+        }
         
-        let url = format!("{}/devdata/{}/latest", self.base_url, device_id);
-        let response = self.authenticated_get(&url).await?;
+        debug!("Product of {} unknown, fetching device", device_id);
+        Ok(self.get_device(device_id).await?.product())
+    }
+    
+    /// Bind a device to the account using its passcode
+    pub async fn bind_device(&self, device_id: &str, passcode: &str, alias: Option<&str>, remark: Option<&str>) -> Result<(), HeatzyError> {
+        let binding = DeviceBinding {
+            did: device_id.to_string(),
+            passcode: passcode.to_string(),
+            alias: alias.map(str::to_string),
+            remark: remark.map(str::to_string),
+        };
         
-        if response.status() == reqwest::StatusCode::NOT_FOUND {
-            return Err(HeatzyError::NotFound(format!("Device '{}' not found", device_id)));
+        let report = self.bind_devices(std::slice::from_ref(&binding)).await?;
+        if !report.success.iter().any(|did| did == device_id) {
+            return Err(HeatzyError::Api(format!("Device '{}' could not be bound", device_id)));
         }
+        Ok(())
+    }
+    
+    /// Bind several devices to the account, reporting the outcome of each
+    pub async fn bind_devices(&self, bindings: &[DeviceBinding]) -> Result<BindingReport, HeatzyError> {
+        info!("Binding {} devices", bindings.len());
+        
+        let url = format!("{}/bindings", self.base_url);
+        let response = self.authenticated_post(&url, &BindRequest { devices: bindings }, false).await?;
         
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(HeatzyError::Api(format!("Failed to get device data with status {}: {}", status, error_text)));
+            return Err(HeatzyError::from_response(response).await);
         }
         
-        let device_data: DeviceDataResponse = response.json().await?;
-        let mode_value = &device_data.attr.mode;
+        let report: BindingReport = response.json().await?;
+        if !report.all_succeeded() {
+            warn!("Failed to bind devices: {}", report.failed.join(", "));
+        }
+        Ok(report)
+    }
+    
+    /// Bind a device to the account using its MAC address
+    ///
+    /// The request is signed with the product secret, which must have been
+    /// registered with [`ClientBuilder::product_secret`] or [`Client::set_product_secret`].
+    pub async fn bind_by_mac(&self, product_key: &str, mac: &str) -> Result<BoundDevice, HeatzyError> {
+        info!("Binding device {} of product {}", mac, product_key);
         
-        trace!("Raw mode value: {:?}", mode_value);
+        let product_secret = self.product_secrets.get(product_key)
+            .ok_or_else(|| HeatzyError::Config(format!("No secret registered for product {}", product_key)))?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+            .to_string();
+        let signature = format!("{:x}", md5::compute(format!("{}{}", product_secret, timestamp)));
         
-        // Try to parse as number first, then as string
-        let mode = if let Some(num) = mode_value.as_i64() {
-            DeviceMode::from_int(num as i32)?
-        } else if let Some(s) = mode_value.as_str() {
-            DeviceMode::from_str_api(s)?
-        } else {
-            return Err(HeatzyError::Api(format!("Invalid mode value: {:?}", mode_value)));
-        };
+        let url = format!("{}/bind_mac", self.base_url);
+        let body = BindMacRequest { product_key, mac };
+        trace!("POST {}", url);
+        let response = self.send_authenticated(|| {
+            self.request(Method::POST, &url)
+                .header(TIMESTAMP_HEADER, &timestamp)
+                .header(SIGNATURE_HEADER, &signature)
+                .json(&body)
+        }, false).await?;
         
-        info!("Device mode: {}", mode);
-        Ok(mode)
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let device: BoundDevice = response.json().await?;
+        info!("Bound device {}", device.did);
+        Ok(device)
     }
     
-    /// Set the mode of a device
-    pub async fn set_device_mode(&self, device_id: &str, mode: DeviceMode) -> Result<(), HeatzyError> {
-        self.ensure_authenticated()?;
-        info!("Setting mode for device {} to {}", device_id, mode);
+    /// Unbind devices from the account, reporting the outcome of each
+    pub async fn unbind_devices(&self, device_ids: &[&str]) -> Result<BindingReport, HeatzyError> {
+        info!("Unbinding {} devices", device_ids.len());
         
-        let url = format!("{}/control/{}", self.base_url, device_id);
-        let control_request = ControlRequest {
-            attrs: ControlAttributes {
-                mode: mode.to_int(),
-            },
+        let url = format!("{}/bindings", self.base_url);
+        let body = UnbindRequest {
+            devices: device_ids.iter().map(|did| UnbindDevice { did }).collect(),
         };
+        trace!("DELETE {}", url);
+        // Repeating the request would report the devices as failed
+        let response = self.send_authenticated(|| self.request(Method::DELETE, &url).json(&body), false).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let report: BindingReport = response.json().await?;
+        if !report.all_succeeded() {
+            warn!("Failed to unbind devices: {}", report.failed.join(", "));
+        }
         
-        debug!("Sending control request with mode: {}", mode.to_int());
-        let response = self.authenticated_post(&url, &control_request).await?;
+        let mut products = self.products.lock().unwrap();
+        for did in &report.success {
+            products.remove(did);
+        }
+        Ok(report)
+    }
+    
+    /// Change the name of a device
+    pub async fn rename_device(&self, device_id: &str, alias: &str) -> Result<(), HeatzyError> {
+        info!("Renaming device {} to {}", device_id, alias);
+        self.update_binding(device_id, BindingUpdate { dev_alias: Some(alias), remark: None }).await
+    }
+    
+    /// Change the remark stored with a device
+    pub async fn set_device_remark(&self, device_id: &str, remark: &str) -> Result<(), HeatzyError> {
+        info!("Setting remark of device {}", device_id);
+        self.update_binding(device_id, BindingUpdate { dev_alias: None, remark: Some(remark) }).await
+    }
+    
+    /// Helper updating the alias and remark of a binding
+    async fn update_binding(&self, device_id: &str, update: BindingUpdate<'_>) -> Result<(), HeatzyError> {
+        let url = format!("{}/bindings/{}", self.base_url, device_id);
+        let response = self.authenticated_put(&url, &update).await?;
         
         if response.status() == reqwest::StatusCode::NOT_FOUND {
             return Err(HeatzyError::NotFound(format!("Device '{}' not found", device_id)));
         }
         
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(HeatzyError::Api(format!("Failed to control device with status {}: {}", status, error_text)));
+            return Err(HeatzyError::from_response(response).await);
         }
         
-        info!("Successfully set device mode");
+        info!("Successfully updated device binding");
         Ok(())
     }
     
-    /// Helper to ensure we have a token
-    fn ensure_authenticated(&self) -> Result<(), HeatzyError> {
-        if self.token.is_none() {
-            return Err(HeatzyError::NoToken);
+    /// Share a device owned by the account
+    pub async fn create_sharing(&self, device_id: &str, target: &ShareTarget) -> Result<SharingInvitation, HeatzyError> {
+        info!("Sharing device {} with {:?}", device_id, target);
+        
+        let url = format!("{}/sharing", self.base_url);
+        // A repeated creation would send a second invitation
+        let response = self.authenticated_post(&url, &SharingRequest::new(device_id, target), false).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
         }
-        Ok(())
+        
+        let invitation: SharingInvitation = response.json().await?;
+        info!("Created sharing {}", invitation.id);
+        Ok(invitation)
     }
     
-    /// Helper for authenticated GET requests
-    async fn authenticated_get(&self, url: &str) -> Result<reqwest::Response, HeatzyError> {
-        let token = self.token.as_ref().ok_or(HeatzyError::NoToken)?;
+    /// List the sharings sent or received by the account
+    pub async fn list_sharings(&self, role: SharingRole) -> Result<Vec<Sharing>, HeatzyError> {
+        info!("Fetching sharings as {:?}", role);
         
-        trace!("GET {}", url);
-        self.http_client
-            .get(url)
-            .header(USER_TOKEN_HEADER, token)
-            .send()
-            .await
-            .map_err(Into::into)
+        let limit = 100;
+        let sharings = self.fetch_all_pages(|skip| async move {
+            let url = format!(
+                "{}/sharing?sharing_type={}&limit={}&skip={}",
+                self.base_url, role.to_int(), limit, skip
+            );
+            let response = self.authenticated_get(&url).await?;
+            
+            if !response.status().is_success() {
+                return Err(HeatzyError::from_response(response).await);
+            }
+            
+            let page: SharingsResponse = response.json().await?;
+            Ok(page.objects)
+        }, |sharing: &Sharing| sharing.id.clone()).await?;
+        
+        info!("Found {} sharings", sharings.len());
+        Ok(sharings)
     }
     
-    /// Helper for authenticated POST requests
-    async fn authenticated_post<T: serde::Serialize>(&self, url: &str, body: &T) -> Result<reqwest::Response, HeatzyError> {
-        let token = self.token.as_ref().ok_or(HeatzyError::NoToken)?;
-        
-        trace!("POST {}", url);
-        self.http_client
-            .post(url)
-            .header(USER_TOKEN_HEADER, token)
-            .json(body)
-            .send()
-            .await
-            .map_err(Into::into)
+    /// Accept a sharing invitation received by the account
+    pub async fn accept_sharing(&self, sharing_id: &str) -> Result<(), HeatzyError> {
+        info!("Accepting sharing {}", sharing_id);
+        self.answer_sharing(sharing_id, true).await
+    }
+    
+    /// Refuse a sharing invitation received by the account
+    pub async fn refuse_sharing(&self, sharing_id: &str) -> Result<(), HeatzyError> {
+        info!("Refusing sharing {}", sharing_id);
+        self.answer_sharing(sharing_id, false).await
     }
-}
\ No newline at end of file
+    
+    /// Accept a sharing from the content of its QR code
+    pub async fn accept_sharing_code(&self, code: &str) -> Result<(), HeatzyError> {
+        info!("Accepting sharing code");
+        
+        let url = format!("{}/sharing/code/{}", self.base_url, code);
+        // Accepting twice has no further effect
+        let response = self.authenticated_post(&url, &serde_json::json!({}), true).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound("Sharing code not found".to_string()));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        info!("Successfully accepted sharing");
+        Ok(())
+    }
+    
+    /// Cancel a sharing invitation sent by the account
+    pub async fn revoke_sharing(&self, sharing_id: &str) -> Result<(), HeatzyError> {
+        info!("Revoking sharing {}", sharing_id);
+        
+        let url = format!("{}/sharing/{}", self.base_url, sharing_id);
+        let response = self.authenticated_delete(&url).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Sharing '{}' not found", sharing_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        info!("Successfully revoked sharing");
+        Ok(())
+    }
+    
+    /// List the accounts a device owned by the account is shared with
+    pub async fn list_guests(&self, device_id: &str) -> Result<Vec<Guest>, HeatzyError> {
+        info!("Fetching guests of device {}", device_id);
+        
+        let url = format!("{}/{}/guests", self.base_url, device_id);
+        let response = self.authenticated_get(&url).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Device '{}' not found", device_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let guests: GuestsResponse = response.json().await?;
+        Ok(guests.users)
+    }
+    
+    /// Remove the access of a guest to a device owned by the account
+    pub async fn remove_guest(&self, device_id: &str, uid: &str) -> Result<(), HeatzyError> {
+        info!("Removing guest {} from device {}", uid, device_id);
+        
+        let url = format!("{}/{}/guests/{}", self.base_url, device_id, uid);
+        let response = self.authenticated_delete(&url).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Guest '{}' of device '{}' not found", uid, device_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        info!("Successfully removed guest");
+        Ok(())
+    }
+    
+    /// Helper accepting or refusing a sharing invitation
+    async fn answer_sharing(&self, sharing_id: &str, accept: bool) -> Result<(), HeatzyError> {
+        let url = format!("{}/sharing/{}?on={}", self.base_url, sharing_id, u8::from(accept));
+        let response = self.authenticated_put(&url, &serde_json::json!({})).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Sharing '{}' not found", sharing_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        Ok(())
+    }
+    
+    /// List the device groups of the account
+    pub async fn list_groups(&self) -> Result<Vec<DeviceGroup>, HeatzyError> {
+        info!("Fetching device groups");
+        
+        let url = format!("{}/group", self.base_url);
+        let response = self.authenticated_get(&url).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let groups: Vec<DeviceGroup> = response.json().await?;
+        info!("Found {} device groups", groups.len());
+        Ok(groups)
+    }
+    
+    /// Create a device group, optionally restricted to one product, returning its id
+    pub async fn create_group(&self, name: &str, product_key: Option<&str>) -> Result<String, HeatzyError> {
+        info!("Creating device group {}", name);
+        
+        let url = format!("{}/group", self.base_url);
+        let body = GroupRequest { group_name: name, product_key };
+        // A repeated creation would create a second group
+        let response = self.authenticated_post(&url, &body, false).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let created: CreatedGroupResponse = response.json().await?;
+        info!("Created device group {}", created.id);
+        Ok(created.id)
+    }
+    
+    /// Rename a device group
+    pub async fn rename_group(&self, group_id: &str, name: &str) -> Result<(), HeatzyError> {
+        info!("Renaming device group {} to {}", group_id, name);
+        
+        let url = format!("{}/group/{}", self.base_url, group_id);
+        let body = GroupRequest { group_name: name, product_key: None };
+        let response = self.authenticated_put(&url, &body).await?;
+        
+        self.check_group_response(group_id, response).await
+    }
+    
+    /// Delete a device group, leaving its devices untouched
+    pub async fn delete_group(&self, group_id: &str) -> Result<(), HeatzyError> {
+        info!("Deleting device group {}", group_id);
+        
+        let url = format!("{}/group/{}", self.base_url, group_id);
+        let response = self.authenticated_delete(&url).await?;
+        
+        self.check_group_response(group_id, response).await
+    }
+    
+    /// List the devices of a group
+    pub async fn list_group_devices(&self, group_id: &str) -> Result<Vec<GroupDevice>, HeatzyError> {
+        info!("Fetching devices of group {}", group_id);
+        
+        let url = format!("{}/group/{}/devices", self.base_url, group_id);
+        let response = self.authenticated_get(&url).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Group '{}' not found", group_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        Ok(response.json().await?)
+    }
+    
+    /// Add devices to a group, reporting the outcome of each
+    pub async fn add_group_devices(&self, group_id: &str, device_ids: &[&str]) -> Result<BindingReport, HeatzyError> {
+        info!("Adding {} devices to group {}", device_ids.len(), group_id);
+        self.update_group_devices(Method::POST, group_id, device_ids).await
+    }
+    
+    /// Remove devices from a group, reporting the outcome of each
+    pub async fn remove_group_devices(&self, group_id: &str, device_ids: &[&str]) -> Result<BindingReport, HeatzyError> {
+        info!("Removing {} devices from group {}", device_ids.len(), group_id);
+        self.update_group_devices(Method::DELETE, group_id, device_ids).await
+    }
+    
+    /// Set the mode of every device of a group
+    ///
+    /// One control is sent per device, encoded for its product, and the outcome
+    /// of each is reported in member order. Controls run concurrently like with
+    /// [`Client::set_modes`]. Only failing to list the members is an error.
+    pub async fn set_group_mode(&self, group_id: &str, mode: DeviceMode) -> Result<Vec<ControlOutcome>, HeatzyError> {
+        info!("Setting mode of group {} to {}", group_id, mode);
+        
+        let devices = self.list_group_devices(group_id).await?;
+        let outcomes = futures::stream::iter(devices)
+            .map(|device| async move {
+                let result = self.set_device_mode(&device.did, mode).await;
+                if let Err(e) = &result {
+                    warn!("Failed to set mode of {}: {}", device.did, e);
+                }
+                ControlOutcome { did: device.did, result }
+            })
+            .buffered(self.max_concurrency)
+            .collect()
+            .await;
+        
+        Ok(outcomes)
+    }
+    
+    /// Helper adding devices to or removing them from a group
+    async fn update_group_devices(&self, method: Method, group_id: &str, device_ids: &[&str]) -> Result<BindingReport, HeatzyError> {
+        let url = format!("{}/group/{}/devices?show_result=1", self.base_url, group_id);
+        let body = GroupDevicesRequest { dids: device_ids };
+        trace!("{} {}", method, url);
+        // Membership changes end in the same state when repeated
+        let response = self.send_authenticated(|| self.request(method.clone(), &url).json(&body), true).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Group '{}' not found", group_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let report: BindingReport = response.json().await?;
+        if !report.all_succeeded() {
+            warn!("Failed to update group devices: {}", report.failed.join(", "));
+        }
+        Ok(report)
+    }
+    
+    /// Helper checking the response of a group update
+    async fn check_group_response(&self, group_id: &str, response: reqwest::Response) -> Result<(), HeatzyError> {
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Group '{}' not found", group_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the latest state reported by a device
+    pub async fn get_device_state(&self, device_id: &str) -> Result<DeviceState, HeatzyError> {
+        info!("Getting state for device: {}", device_id);
+        
+        let device_data = self.latest_device_data(device_id).await?;
+        DeviceState::from_attributes(device_id, device_data.attr, device_data.updated_at)
+    }
+    
+    /// Get the current mode of a device
+    pub async fn get_device_mode(&self, device_id: &str) -> Result<DeviceMode, HeatzyError> {
+        info!("Getting mode for device: {}", device_id);
+        
+        let mode = self.get_device_state(device_id).await?
+            .mode
+            .ok_or_else(|| HeatzyError::Api(format!("Device '{}' did not report a mode", device_id)))?;
+        
+        info!("Device mode: {}", mode);
+        Ok(mode)
+    }
+    
+    /// Set the mode of a device
+    ///
+    /// The mode is encoded the way the device generation expects it.
+    pub async fn set_device_mode(&self, device_id: &str, mode: DeviceMode) -> Result<(), HeatzyError> {
+        info!("Setting mode for device {} to {}", device_id, mode);
+        
+        let product = self.get_device_product(device_id).await?;
+        if !product.capabilities().supports_mode(mode) {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support the {} mode", product, mode)));
+        }
+        
+        let mode_value = mode.to_api_value(product)?;
+        debug!("Sending control request with mode: {}", mode_value);
+        self.control(device_id, &ControlAttributes::new().with("mode", mode_value)).await?;
+        
+        info!("Successfully set device mode");
+        Ok(())
+    }
+    
+    /// Set the mode of several devices at once
+    ///
+    /// Requests run concurrently, up to the limit set with
+    /// [`ClientBuilder::max_concurrency`], and are retried according to the
+    /// retry policy. Devices the API reports offline are skipped; see
+    /// [`ClientBuilder::check_online`] to leave them out upfront.
+    pub async fn set_modes<I, D>(&self, modes: I) -> BatchReport
+    where
+        I: IntoIterator<Item = (D, DeviceMode)>,
+        D: Into<String>,
+    {
+        let modes = modes.into_iter().map(|(did, mode)| (did.into(), mode)).collect();
+        self.run_batch(modes, |did, mode| async move {
+            self.set_device_mode(&did, mode).await
+        }).await
+    }
+    
+    /// Get the mode of several devices at once
+    ///
+    /// Requests run concurrently like with [`Client::set_modes`].
+    pub async fn get_modes(&self, device_ids: &[&str]) -> BatchReport<DeviceMode> {
+        let device_ids = device_ids.iter().map(|did| (did.to_string(), ())).collect();
+        self.run_batch(device_ids, |did, ()| async move {
+            self.get_device_mode(&did).await
+        }).await
+    }
+    
+    /// Watch devices by polling their state, as a fallback when the websocket is unavailable
+    ///
+    /// Yields the same events as [`WebSocketSession`], except
+    /// [`DeviceEvent::Disconnected`] and [`DeviceEvent::Reconnected`]: the first
+    /// report of each device in full, then only the attributes that changed, and
+    /// connectivity changes. Unchanged reports are dropped. Devices that stay
+    /// unchanged, offline or failing are polled less often, up to 8 times the
+    /// interval, and back at the interval as soon as they change. Connectivity
+    /// is refreshed every 4 intervals by listing the devices. Errors are
+    /// yielded without ending the stream.
+    pub fn watch<'a>(&'a self, device_ids: &[&str], interval: Duration) -> impl Stream<Item = Result<DeviceEvent, HeatzyError>> + 'a {
+        info!("Watching {} devices every {:?}", device_ids.len(), interval);
+        let watcher = Watcher::new(device_ids, interval);
+        
+        futures::stream::unfold(watcher, move |mut watcher| async move {
+            loop {
+                if let Some(event) = watcher.pending.pop_front() {
+                    return Some((event, watcher));
+                }
+                
+                tokio::time::sleep_until(watcher.next_wake_up()).await;
+                let now = tokio::time::Instant::now();
+                
+                if watcher.listing_due(now) {
+                    match self.list_devices().await {
+                        Ok(devices) => {
+                            let online = devices.iter().map(|device| (device.did.as_str(), device.is_online));
+                            watcher.record_listing(online, now);
+                        }
+                        Err(e) => {
+                            warn!("Failed to check which devices are online: {}", e);
+                            watcher.record_listing_error(e, now);
+                        }
+                    }
+                }
+                
+                let reports: Vec<_> = futures::stream::iter(watcher.due(now))
+                    .map(|did| async move {
+                        let report = self.latest_device_data(&did).await;
+                        (did, report)
+                    })
+                    .buffer_unordered(self.max_concurrency)
+                    .collect()
+                    .await;
+                
+                for (did, report) in reports {
+                    match report {
+                        Ok(data) => watcher.record_attributes(&did, data.attr, data.updated_at, now),
+                        Err(e) => {
+                            debug!("Failed to poll {}: {}", did, e);
+                            watcher.record_error(&did, e, now);
+                        }
+                    }
+                }
+            }
+        })
+    }
+    
+    /// Start a boost (comfort mode) for the given duration, rounded up to the minute
+    pub async fn set_boost(&self, device_id: &str, duration: Duration) -> Result<(), HeatzyError> {
+        info!("Starting {:?} boost on device {}", duration, device_id);
+        
+        let product = self.get_device_product(device_id).await?;
+        let capabilities = product.capabilities();
+        if !capabilities.boost {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support boost", product)));
+        }
+        
+        let minutes = duration.as_secs().div_ceil(60);
+        if minutes == 0 || minutes > u64::from(capabilities.max_boost_minutes) {
+            return Err(HeatzyError::InvalidValue(format!(
+                "Boost must last between 1 and {} minutes, got {}", capabilities.max_boost_minutes, minutes
+            )));
+        }
+        
+        self.set_derogation(device_id, DerogationMode::Boost, minutes as u32).await
+    }
+    
+    /// Switch to frost protection for the given number of days
+    pub async fn set_vacation(&self, device_id: &str, days: u32) -> Result<(), HeatzyError> {
+        info!("Starting {} days vacation on device {}", days, device_id);
+        
+        let product = self.get_device_product(device_id).await?;
+        let capabilities = product.capabilities();
+        if !capabilities.vacation {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support vacation", product)));
+        }
+        
+        if days == 0 || days > capabilities.max_vacation_days {
+            return Err(HeatzyError::InvalidValue(format!(
+                "Vacation must last between 1 and {} days, got {}", capabilities.max_vacation_days, days
+            )));
+        }
+        
+        self.set_derogation(device_id, DerogationMode::Vacation, days).await
+    }
+    
+    /// Enable or disable presence detection
+    ///
+    /// Disabling it cancels any running derogation.
+    pub async fn set_presence_detection(&self, device_id: &str, enabled: bool) -> Result<(), HeatzyError> {
+        info!("Setting presence detection on device {} to {}", device_id, enabled);
+        
+        let product = self.get_device_product(device_id).await?;
+        if !product.capabilities().presence_detection {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support presence detection", product)));
+        }
+        
+        let mode = if enabled { DerogationMode::Presence } else { DerogationMode::Off };
+        self.set_derogation(device_id, mode, 0).await
+    }
+    
+    /// Cancel a running boost, vacation or presence detection
+    pub async fn cancel_derogation(&self, device_id: &str) -> Result<(), HeatzyError> {
+        info!("Cancelling derogation on device {}", device_id);
+        self.set_derogation(device_id, DerogationMode::Off, 0).await
+    }
+    
+    /// Get the ambient temperature and humidity measured by a device
+    pub async fn get_sensors(&self, device_id: &str) -> Result<Sensors, HeatzyError> {
+        info!("Getting sensors for device: {}", device_id);
+        
+        let product = self.get_device_product(device_id).await?;
+        let capabilities = product.capabilities();
+        if !capabilities.temperature_sensor && !capabilities.humidity_sensor {
+            return Err(HeatzyError::Unsupported(format!("{} devices have no sensors", product)));
+        }
+        
+        Ok(self.get_device_state(device_id).await?.sensors())
+    }
+    
+    /// Check whether the child lock of a device is enabled
+    pub async fn get_child_lock(&self, device_id: &str) -> Result<bool, HeatzyError> {
+        self.get_device_state(device_id).await?
+            .lock_enabled
+            .ok_or_else(|| HeatzyError::Api(format!("Device '{}' did not report its child lock", device_id)))
+    }
+    
+    /// Enable or disable the child lock of a device
+    pub async fn set_child_lock(&self, device_id: &str, enabled: bool) -> Result<(), HeatzyError> {
+        info!("Setting child lock on device {} to {}", device_id, enabled);
+        
+        let product = self.get_device_product(device_id).await?;
+        if !product.capabilities().child_lock {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support child lock", product)));
+        }
+        
+        let attrs = ControlAttributes::new().with(product.lock_attribute(), u8::from(enabled));
+        self.control(device_id, &attrs).await?;
+        
+        info!("Successfully set child lock");
+        Ok(())
+    }
+    
+    /// Check whether open-window detection is enabled on a device
+    pub async fn get_window_detection(&self, device_id: &str) -> Result<bool, HeatzyError> {
+        self.get_device_state(device_id).await?
+            .window_detection_enabled
+            .ok_or_else(|| HeatzyError::Api(format!("Device '{}' did not report its window detection", device_id)))
+    }
+    
+    /// Enable or disable open-window detection on a device
+    pub async fn set_window_detection(&self, device_id: &str, enabled: bool) -> Result<(), HeatzyError> {
+        info!("Setting window detection on device {} to {}", device_id, enabled);
+        
+        let product = self.get_device_product(device_id).await?;
+        if !product.capabilities().window_detection {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support window detection", product)));
+        }
+        
+        let attrs = ControlAttributes::new().with("window_switch", u8::from(enabled));
+        self.control(device_id, &attrs).await?;
+        
+        info!("Successfully set window detection");
+        Ok(())
+    }
+    
+    /// Get the weekly programme stored on a device
+    pub async fn get_schedule(&self, device_id: &str) -> Result<WeeklySchedule, HeatzyError> {
+        info!("Getting schedule for device: {}", device_id);
+        
+        let product = self.get_device_product(device_id).await?;
+        if product.capabilities().schedule.is_none() {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support schedules", product)));
+        }
+        
+        let state = self.get_device_state(device_id).await?;
+        WeeklySchedule::from_attributes(&state.attributes)
+    }
+    
+    /// Replace the weekly programme stored on a device
+    ///
+    /// This does not enable the programme, see [`Client::set_schedule_enabled`].
+    pub async fn set_schedule(&self, device_id: &str, schedule: &WeeklySchedule) -> Result<(), HeatzyError> {
+        info!("Setting schedule for device: {}", device_id);
+        
+        let product = self.get_device_product(device_id).await?;
+        if product.capabilities().schedule.is_none() {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support schedules", product)));
+        }
+        
+        self.control(device_id, &schedule.to_attributes()).await?;
+        
+        info!("Successfully set schedule");
+        Ok(())
+    }
+    
+    /// Enable or disable the weekly programme of a device
+    pub async fn set_schedule_enabled(&self, device_id: &str, enabled: bool) -> Result<(), HeatzyError> {
+        info!("Setting schedule on device {} to {}", device_id, enabled);
+        
+        let product = self.get_device_product(device_id).await?;
+        if product.capabilities().schedule.is_none() {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support schedules", product)));
+        }
+        
+        let attrs = ControlAttributes::new().with("timer_switch", u8::from(enabled));
+        self.control(device_id, &attrs).await?;
+        
+        info!("Successfully set schedule state");
+        Ok(())
+    }
+    
+    /// List the scheduler tasks of the account
+    ///
+    /// Tasks in a format this library does not understand are skipped with a warning.
+    pub async fn list_scheduled_tasks(&self) -> Result<Vec<ScheduledTask>, HeatzyError> {
+        info!("Fetching scheduler tasks");
+        
+        let limit = 100;
+        let responses = self.fetch_all_pages(|skip| async move {
+            let url = format!("{}/scheduler?limit={}&skip={}", self.base_url, limit, skip);
+            let response = self.authenticated_get(&url).await?;
+            
+            if !response.status().is_success() {
+                return Err(HeatzyError::from_response(response).await);
+            }
+            
+            let page: Vec<SchedulerResponse> = response.json().await?;
+            Ok(page)
+        }, |task: &SchedulerResponse| task.id.clone()).await?;
+        
+        let mut tasks = Vec::new();
+        for task in responses {
+            // Tasks created by other apps may use formats not understood here
+            let id = task.id.clone();
+            match ScheduledTask::try_from(task) {
+                Ok(task) => tasks.push(task),
+                Err(e) => warn!("Skipping scheduler task {}: {}", id, e),
+            }
+        }
+        
+        info!("Found {} scheduler tasks", tasks.len());
+        Ok(tasks)
+    }
+    
+    /// Create a scheduler task writing to a device, returning its id
+    pub async fn create_scheduled_task(&self, device_id: &str, task: &NewScheduledTask) -> Result<String, HeatzyError> {
+        info!("Creating scheduler task for device {}", device_id);
+        
+        let attrs = self.task_attributes(device_id, &task.action).await?;
+        let request = task.to_request(device_id, attrs)?;
+        let url = format!("{}/scheduler", self.base_url);
+        
+        // A repeated creation would schedule the task twice
+        let response = self.authenticated_post(&url, &request, false).await?;
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let created: CreatedResponse = response.json().await?;
+        info!("Created scheduler task {}", created.id);
+        Ok(created.id)
+    }
+    
+    /// Replace a scheduler task
+    pub async fn update_scheduled_task(&self, task_id: &str, device_id: &str, task: &NewScheduledTask) -> Result<(), HeatzyError> {
+        info!("Updating scheduler task {}", task_id);
+        
+        let attrs = self.task_attributes(device_id, &task.action).await?;
+        let request = task.to_request(device_id, attrs)?;
+        let url = format!("{}/scheduler/{}", self.base_url, task_id);
+        let response = self.authenticated_put(&url, &request).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Scheduler task '{}' not found", task_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        info!("Successfully updated scheduler task");
+        Ok(())
+    }
+    
+    /// Delete a scheduler task
+    pub async fn delete_scheduled_task(&self, task_id: &str) -> Result<(), HeatzyError> {
+        info!("Deleting scheduler task {}", task_id);
+        
+        let url = format!("{}/scheduler/{}", self.base_url, task_id);
+        let response = self.authenticated_delete(&url).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Scheduler task '{}' not found", task_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        info!("Successfully deleted scheduler task");
+        Ok(())
+    }
+    
+    /// Helper encoding a scheduler task action for the device product
+    async fn task_attributes(&self, device_id: &str, action: &TaskAction) -> Result<ControlAttributes, HeatzyError> {
+        match action {
+            TaskAction::Mode(mode) => {
+                let product = self.get_device_product(device_id).await?;
+                if !product.capabilities().supports_mode(*mode) {
+                    return Err(HeatzyError::Unsupported(format!("{} devices do not support the {} mode", product, mode)));
+                }
+                Ok(ControlAttributes::new().with("mode", mode.to_api_value(product)?))
+            }
+            TaskAction::Attributes(attrs) => Ok(attrs.clone()),
+        }
+    }
+    
+    /// Get the comfort and eco setpoints of a thermostat-capable device
+    pub async fn get_setpoints(&self, device_id: &str) -> Result<Setpoints, HeatzyError> {
+        info!("Getting setpoints for device: {}", device_id);
+        Ok(self.get_device_state(device_id).await?.setpoints())
+    }
+    
+    /// Set the comfort and/or eco setpoints of a thermostat-capable device
+    ///
+    /// Setpoints left to `None` are not changed. The eco setpoint may not be
+    /// above the comfort one; when only one is given, the current value of the
+    /// other is read to check it.
+    pub async fn set_setpoints(&self, device_id: &str, setpoints: Setpoints) -> Result<(), HeatzyError> {
+        info!("Setting setpoints for device {}: {:?}", device_id, setpoints);
+        
+        let product = self.get_device_product(device_id).await?;
+        let capabilities = product.capabilities();
+        let Some(encoding) = capabilities.setpoints else {
+            return Err(HeatzyError::Unsupported(format!("{} devices do not support setpoints", product)));
+        };
+        
+        let mut attrs = ControlAttributes::new();
+        for (name, temperature) in [("cft_temp", setpoints.comfort), ("eco_temp", setpoints.eco)] {
+            let Some(temperature) = temperature else {
+                continue;
+            };
+            if !capabilities.supports_setpoint(temperature) {
+                return Err(HeatzyError::InvalidValue(format!(
+                    "Setpoint must be between {} and {}, got {}", capabilities.min_setpoint, capabilities.max_setpoint, temperature
+                )));
+            }
+            encode_temperature(&mut attrs, name, temperature, encoding);
+        }
+        
+        if attrs.is_empty() {
+            return Ok(());
+        }
+        
+        // Check a single setpoint against the current value of the other one
+        let mut resulting = setpoints;
+        if setpoints.comfort.is_none() || setpoints.eco.is_none() {
+            let current = self.get_device_state(device_id).await?.setpoints();
+            resulting.comfort = resulting.comfort.or(current.comfort);
+            resulting.eco = resulting.eco.or(current.eco);
+        }
+        if let (Some(comfort), Some(eco)) = (resulting.comfort, resulting.eco) {
+            if eco > comfort {
+                return Err(HeatzyError::InvalidValue(format!(
+                    "Eco setpoint {} is above comfort setpoint {}", eco, comfort
+                )));
+            }
+        }
+        
+        debug!("Sending setpoint attributes: {:?}", attrs);
+        self.control(device_id, &attrs).await?;
+        
+        info!("Successfully set setpoints");
+        Ok(())
+    }
+    
+    /// Set the comfort setpoint of a thermostat-capable device
+    pub async fn set_comfort_setpoint(&self, device_id: &str, temperature: Temperature) -> Result<(), HeatzyError> {
+        self.set_setpoints(device_id, Setpoints { comfort: Some(temperature), eco: None }).await
+    }
+    
+    /// Set the eco setpoint of a thermostat-capable device
+    pub async fn set_eco_setpoint(&self, device_id: &str, temperature: Temperature) -> Result<(), HeatzyError> {
+        self.set_setpoints(device_id, Setpoints { comfort: None, eco: Some(temperature) }).await
+    }
+    
+    /// Helper writing the derogation attributes
+    async fn set_derogation(&self, device_id: &str, mode: DerogationMode, time: u32) -> Result<(), HeatzyError> {
+        let mut attrs = ControlAttributes::new().with("derog_mode", mode.to_int());
+        if mode != DerogationMode::Off {
+            attrs.insert("derog_time", time);
+        }
+        
+        debug!("Sending derogation {} with time {}", mode, time);
+        self.control(device_id, &attrs).await?;
+        
+        info!("Successfully set derogation");
+        Ok(())
+    }
+    
+    /// Write arbitrary attributes to a device
+    ///
+    /// The attributes are sent as is, without checking the device supports them.
+    pub async fn control(&self, device_id: &str, attrs: &ControlAttributes) -> Result<(), HeatzyError> {
+        trace!("Writing attributes to {}: {:?}", device_id, attrs);
+        let url = format!("{}/control/{}", self.base_url, device_id);
+        let control_request = ControlRequest { attrs };
+        
+        // Writing absolute attribute values can safely be repeated
+        let response = self.authenticated_post(&url, &control_request, true).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Device '{}' not found", device_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        Ok(())
+    }
+    
+    /// Helper running an operation on several devices with bounded concurrency
+    async fn run_batch<A, T, F, Fut>(&self, items: Vec<(String, A)>, operation: F) -> BatchReport<T>
+    where
+        F: Fn(String, A) -> Fut,
+        Fut: Future<Output = Result<T, HeatzyError>>,
+    {
+        info!("Running batch operation on {} devices", items.len());
+        let mut report = BatchReport::default();
+        
+        // Devices missing from the list are still tried and fail on their own
+        let online: HashMap<String, bool> = if !self.check_online {
+            HashMap::new()
+        } else {
+            match self.list_devices().await {
+                Ok(devices) => devices.into_iter().map(|device| (device.did, device.is_online)).collect(),
+                Err(e) => {
+                    warn!("Failed to check which devices are online: {}", e);
+                    HashMap::new()
+                }
+            }
+        };
+        
+        let mut pending = Vec::new();
+        for (did, argument) in items {
+            if online.get(&did) == Some(&false) {
+                debug!("Skipping offline device {}", did);
+                report.skipped_offline.push(did);
+            } else {
+                pending.push((did, argument));
+            }
+        }
+        
+        let mut results = futures::stream::iter(pending)
+            .map(|(did, argument)| {
+                let result = operation(did.clone(), argument);
+                async move { (did, result.await) }
+            })
+            .buffer_unordered(self.max_concurrency);
+        
+        while let Some((did, result)) = results.next().await {
+            match result {
+                Ok(value) => report.successes.push((did, value)),
+                Err(e) if e.is_device_offline() => report.skipped_offline.push(did),
+                Err(e) => {
+                    warn!("Batch operation failed on {}: {}", did, e);
+                    report.failures.push((did, e));
+                }
+            }
+        }
+        
+        info!(
+            "Batch operation done: {} succeeded, {} failed, {} offline",
+            report.successes.len(), report.failures.len(), report.skipped_offline.len()
+        );
+        report
+    }
+    
+    /// Helper fetching the last attributes reported by a device
+    async fn latest_device_data(&self, device_id: &str) -> Result<DeviceDataResponse, HeatzyError> {
+        let url = format!("{}/devdata/{}/latest", self.base_url, device_id);
+        let response = self.authenticated_get(&url).await?;
+        
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(HeatzyError::NotFound(format!("Device '{}' not found", device_id)));
+        }
+        
+        if !response.status().is_success() {
+            return Err(HeatzyError::from_response(response).await);
+        }
+        
+        let device_data: DeviceDataResponse = response.json().await?;
+        trace!("Raw attributes: {:?}", device_data.attr);
+        Ok(device_data)
+    }
+    
+    /// Helper to record the product of listed or fetched devices
+    fn remember_products(&self, devices: &[Device]) {
+        let mut products = self.products.lock().unwrap();
+        for device in devices {
+            products.insert(device.did.clone(), device.product());
+        }
+    }
+    
+    /// Helper returning a valid token, logging in first when it is missing or about to expire
+    async fn access_token(&self) -> Result<String, HeatzyError> {
+        if self.session.read().await.is_none() {
+            self.restore_session().await;
+        }
+        
+        if let Some(session) = self.session.read().await.as_ref() {
+            if !self.has_credentials() || !session.expires_within(self.renew_margin) {
+                return Ok(session.token.clone());
+            }
+            debug!("Token expires soon, renewing it");
+        }
+        
+        if !self.has_credentials() {
+            return Err(HeatzyError::NoToken);
+        }
+        self.renew_session(None).await
+    }
+    
+    /// Helper to check whether the client can log in by itself
+    fn has_credentials(&self) -> bool {
+        self.credentials.lock().unwrap().is_some()
+    }
+    
+    /// Helper to log in again with the stored credentials
+    ///
+    /// `rejected_token` is the token the API refused, if any; the session is left
+    /// untouched when another task already replaced it in the meantime.
+    async fn renew_session(&self, rejected_token: Option<&str>) -> Result<String, HeatzyError> {
+        let credentials = self.credentials.lock().unwrap().clone().ok_or(HeatzyError::NoToken)?;
+        let mut session = self.session.write().await;
+        
+        if let Some(current) = session.as_ref() {
+            let replaced = rejected_token.is_some_and(|token| token != current.token);
+            if replaced || (rejected_token.is_none() && !current.expires_within(self.renew_margin)) {
+                return Ok(current.token.clone());
+            }
+        }
+        
+        if rejected_token.is_some() {
+            self.clear_cached_session();
+        }
+        
+        let auth_response = self.login(&credentials.username, &credentials.password).await?;
+        self.cache_session(&auth_response);
+        let token = auth_response.token.clone();
+        *session = Some(auth_response.into());
+        Ok(token)
+    }
+    
+    /// Helper to load the cached token into the session if there is none yet
+    async fn restore_session(&self) {
+        if self.token_store.is_none() {
+            return;
+        }
+        
+        let mut session = self.session.write().await;
+        if session.is_none() {
+            *session = self.cached_session();
+        }
+    }
+    
+    /// Helper to read the cached session of the account, skipping expired tokens
+    fn cached_session(&self) -> Option<Session> {
+        let (token_store, account) = (self.token_store.as_ref()?, self.account.as_ref()?);
+        
+        match token_store.load(&self.token_key(account)) {
+            Ok(auth_response) => auth_response
+                .map(Session::from)
+                .filter(|session| !session.expires_within(Duration::ZERO)),
+            Err(e) => {
+                warn!("Failed to load cached token: {}", e);
+                None
+            }
+        }
+    }
+    
+    /// Helper to save a fresh token in the token store
+    fn cache_session(&self, auth_response: &AuthResponse) {
+        if let (Some(token_store), Some(account)) = (&self.token_store, &self.account) {
+            if let Err(e) = token_store.save(&self.token_key(account), auth_response) {
+                warn!("Failed to cache token: {}", e);
+            }
+        }
+    }
+    
+    /// Helper to drop a token rejected by the API from the token store
+    fn clear_cached_session(&self) {
+        if let (Some(token_store), Some(account)) = (&self.token_store, &self.account) {
+            if let Err(e) = token_store.clear(&self.token_key(account)) {
+                warn!("Failed to clear cached token: {}", e);
+            }
+        }
+    }
+    
+    /// Helper to start a request carrying the application id
+    fn request(&self, method: Method, url: &str) -> reqwest::RequestBuilder {
+        self.http_client
+            .request(method, url)
+            .header(APP_ID_HEADER, self.app_id.clone())
+    }
+    
+    /// Helper for authenticated GET requests
+    async fn authenticated_get(&self, url: &str) -> Result<reqwest::Response, HeatzyError> {
+        trace!("GET {}", url);
+        self.send_authenticated(|| self.request(Method::GET, url), true).await
+    }
+    
+    /// Helper for authenticated POST requests
+    ///
+    /// `idempotent` tells whether the request may be retried after reaching the server.
+    async fn authenticated_post<T: serde::Serialize>(&self, url: &str, body: &T, idempotent: bool) -> Result<reqwest::Response, HeatzyError> {
+        trace!("POST {}", url);
+        self.send_authenticated(|| self.request(Method::POST, url).json(body), idempotent).await
+    }
+    
+    /// Helper for authenticated PUT requests, always safe to retry
+    async fn authenticated_put<T: serde::Serialize>(&self, url: &str, body: &T) -> Result<reqwest::Response, HeatzyError> {
+        trace!("PUT {}", url);
+        self.send_authenticated(|| self.request(Method::PUT, url).json(body), true).await
+    }
+    
+    /// Helper for authenticated DELETE requests, always safe to retry
+    async fn authenticated_delete(&self, url: &str) -> Result<reqwest::Response, HeatzyError> {
+        trace!("DELETE {}", url);
+        self.send_authenticated(|| self.request(Method::DELETE, url), true).await
+    }
+    
+    /// Helper sending a request with the user token
+    ///
+    /// Transient failures are retried according to the retry policy. When the API
+    /// rejects the token and credentials are available, the client logs in again
+    /// and retries the request once.
+    async fn send_authenticated<F>(&self, build: F, idempotent: bool) -> Result<reqwest::Response, HeatzyError>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let mut token = self.access_token().await?;
+        let mut token_renewed = false;
+        let mut attempt = 1;
+        
+        loop {
+            let can_retry = attempt < self.retry_policy.max_attempts;
+            
+            let response = match build().header(USER_TOKEN_HEADER, &token).send().await {
+                Ok(response) => response,
+                Err(e) if can_retry && self.retry_policy.retries_error(&e, idempotent) => {
+                    let delay = self.retry_policy.backoff(attempt);
+                    warn!("Request failed ({}), retrying in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            
+            let status = response.status();
+            if can_retry && self.retry_policy.retries_status(status, idempotent) {
+                let delay = self.retry_policy.response_delay(attempt, &response);
+                warn!("Request failed with status {}, retrying in {:?}", status, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            
+            if token_renewed || !self.has_credentials() || !matches!(status.as_u16(), 400 | 401 | 403) {
+                return Ok(response);
+            }
+            
+            let error = HeatzyError::from_response(response).await;
+            if !error.is_token_invalid() {
+                return Err(error);
+            }
+            
+            info!("Token rejected by the API, logging in again");
+            token = self.renew_session(Some(&token)).await?;
+            token_renewed = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_selects_rest_and_websocket_urls() {
+        let client = Client::builder().build().unwrap();
+        assert_eq!(client.base_url, "https://euapi.gizwits.com/app");
+        assert_eq!(client.websocket_url, "wss://eum2m.gizwits.com:8880/ws/app/v1");
+        assert_eq!(client.token_key("user").region, "eu");
+
+        let client = Client::builder().region(Region::UnitedStates).build().unwrap();
+        assert_eq!(client.base_url, "https://usapi.gizwits.com/app");
+        assert_eq!(client.websocket_url, "wss://usm2m.gizwits.com:8880/ws/app/v1");
+        assert_eq!(client.token_key("user").region, "us");
+    }
+
+    #[test]
+    fn custom_urls_take_precedence_over_the_region() {
+        let client = Client::builder()
+            .region(Region::China)
+            .base_url("http://localhost:8080/app/")
+            .build()
+            .unwrap();
+        assert_eq!(client.base_url, "http://localhost:8080/app");
+        assert_eq!(client.websocket_url, "wss://m2m.gizwits.com:8880/ws/app/v1");
+        assert_eq!(client.token_key("user").region, "http://localhost:8080/app/");
+
+        let client = Client::builder()
+            .region(Region::China)
+            .base_url("http://localhost:8080/app")
+            .websocket_url("ws://localhost:8081/ws")
+            .build()
+            .unwrap();
+        assert_eq!(client.base_url, "http://localhost:8080/app");
+        assert_eq!(client.websocket_url, "ws://localhost:8081/ws");
+    }
+}
@@ -1,5 +1,17 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::fmt;
 use thiserror::Error;
 
+/// Error body returned by the Gizwits API
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error_code: u32,
+    #[serde(default)]
+    error_message: String,
+    detail_message: Option<String>,
+}
+
 #[derive(Error, Debug)]
 pub enum HeatzyError {
     #[error("Network error: {0}")]
@@ -19,4 +31,234 @@ pub enum HeatzyError {
     
     #[error("API error: {0}")]
     Api(String),
-}
\ No newline at end of file
+    
+    #[error("Gizwits error {code} (HTTP {status}): {message}")]
+    Gizwits {
+        status: StatusCode,
+        code: GizwitsErrorCode,
+        message: String,
+        detail: Option<String>,
+    },
+    
+    #[error("HTTP error {status}: {body}")]
+    Http {
+        status: StatusCode,
+        body: String,
+    },
+    
+    #[error("Invalid value: {0}")]
+    InvalidValue(String),
+    
+    #[error("Unsupported by this device: {0}")]
+    Unsupported(String),
+    
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+    
+    #[error("Token store error: {0}")]
+    TokenStore(String),
+    
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    
+    #[error("Invalid schedule at line {line}, column {column}: {message}")]
+    ScheduleSyntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for HeatzyError {
+    fn from(error: tokio_tungstenite::tungstenite::Error) -> Self {
+        HeatzyError::WebSocket(Box::new(error))
+    }
+}
+
+impl HeatzyError {
+    /// Build the error matching a non-2xx response
+    pub(crate) async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        Self::from_body(status, body)
+    }
+
+    /// Helper building the error from the status and body of a failed response
+    fn from_body(status: StatusCode, body: String) -> Self {
+        match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(error) => HeatzyError::Gizwits {
+                status,
+                code: GizwitsErrorCode::from_code(error.error_code),
+                message: error.error_message,
+                detail: error.detail_message,
+            },
+            Err(_) => HeatzyError::Http { status, body },
+        }
+    }
+
+    /// HTTP status of the failed response, if the error comes from one
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            HeatzyError::Gizwits { status, .. } | HeatzyError::Http { status, .. } => Some(*status),
+            HeatzyError::Network(e) => e.status(),
+            _ => None,
+        }
+    }
+
+    /// Gizwits error code, if the API returned one
+    pub fn gizwits_code(&self) -> Option<GizwitsErrorCode> {
+        match self {
+            HeatzyError::Gizwits { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Check whether the user token was rejected
+    pub fn is_token_invalid(&self) -> bool {
+        matches!(self.gizwits_code(), Some(GizwitsErrorCode::TokenInvalid | GizwitsErrorCode::TokenExpired))
+    }
+
+    /// Check whether the request failed because the device is offline
+    pub fn is_device_offline(&self) -> bool {
+        self.gizwits_code() == Some(GizwitsErrorCode::DeviceOffline)
+    }
+
+    /// Check whether the failure is transient and the request may succeed if sent again
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            HeatzyError::Network(e) => e.is_timeout() || e.is_connect(),
+            HeatzyError::Gizwits { status, .. } | HeatzyError::Http { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Error code returned by the Gizwits API in the `error_code` field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizwitsErrorCode {
+    TokenInvalid,       // 9004
+    UserNotExist,       // 9005
+    TokenExpired,       // 9006
+    DeviceNotFound,     // 9014
+    DeviceNotBound,     // 9015
+    DeviceOffline,      // 9017
+    InvalidCredentials, // 9020
+    Other(u32),
+}
+
+impl GizwitsErrorCode {
+    /// Convert from API integer value
+    pub fn from_code(code: u32) -> Self {
+        match code {
+            9004 => GizwitsErrorCode::TokenInvalid,
+            9005 => GizwitsErrorCode::UserNotExist,
+            9006 => GizwitsErrorCode::TokenExpired,
+            9014 => GizwitsErrorCode::DeviceNotFound,
+            9015 => GizwitsErrorCode::DeviceNotBound,
+            9017 => GizwitsErrorCode::DeviceOffline,
+            9020 => GizwitsErrorCode::InvalidCredentials,
+            other => GizwitsErrorCode::Other(other),
+        }
+    }
+
+    /// Convert to API integer value
+    pub fn code(&self) -> u32 {
+        match self {
+            GizwitsErrorCode::TokenInvalid => 9004,
+            GizwitsErrorCode::UserNotExist => 9005,
+            GizwitsErrorCode::TokenExpired => 9006,
+            GizwitsErrorCode::DeviceNotFound => 9014,
+            GizwitsErrorCode::DeviceNotBound => 9015,
+            GizwitsErrorCode::DeviceOffline => 9017,
+            GizwitsErrorCode::InvalidCredentials => 9020,
+            GizwitsErrorCode::Other(code) => *code,
+        }
+    }
+}
+
+impl fmt::Display for GizwitsErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_error_body_becomes_gizwits_error() {
+        let body = r#"{"error_code": 9004, "error_message": "token invalid!", "detail_message": null}"#;
+        let error = HeatzyError::from_body(StatusCode::BAD_REQUEST, body.to_string());
+
+        match &error {
+            HeatzyError::Gizwits { status, code, message, detail } => {
+                assert_eq!(*status, StatusCode::BAD_REQUEST);
+                assert_eq!(*code, GizwitsErrorCode::TokenInvalid);
+                assert_eq!(message, "token invalid!");
+                assert_eq!(*detail, None);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(error.is_token_invalid());
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn other_body_becomes_http_error() {
+        let error = HeatzyError::from_body(StatusCode::BAD_GATEWAY, "<html>Bad gateway</html>".to_string());
+
+        match &error {
+            HeatzyError::Http { status, body } => {
+                assert_eq!(*status, StatusCode::BAD_GATEWAY);
+                assert_eq!(body, "<html>Bad gateway</html>");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(error.gizwits_code(), None);
+    }
+
+    #[test]
+    fn error_codes_map_to_variants() {
+        let known = [
+            (9004, GizwitsErrorCode::TokenInvalid),
+            (9005, GizwitsErrorCode::UserNotExist),
+            (9006, GizwitsErrorCode::TokenExpired),
+            (9014, GizwitsErrorCode::DeviceNotFound),
+            (9015, GizwitsErrorCode::DeviceNotBound),
+            (9017, GizwitsErrorCode::DeviceOffline),
+            (9020, GizwitsErrorCode::InvalidCredentials),
+        ];
+        for (number, code) in known {
+            assert_eq!(GizwitsErrorCode::from_code(number), code);
+            assert_eq!(code.code(), number);
+        }
+
+        assert_eq!(GizwitsErrorCode::from_code(9999), GizwitsErrorCode::Other(9999));
+        assert_eq!(GizwitsErrorCode::Other(9999).code(), 9999);
+    }
+
+    #[test]
+    fn only_server_errors_and_throttling_are_retriable() {
+        let http = |status| HeatzyError::Http { status, body: String::new() };
+        let gizwits = |status| HeatzyError::Gizwits {
+            status,
+            code: GizwitsErrorCode::Other(9999),
+            message: String::new(),
+            detail: None,
+        };
+
+        assert!(http(StatusCode::INTERNAL_SERVER_ERROR).is_retriable());
+        assert!(http(StatusCode::SERVICE_UNAVAILABLE).is_retriable());
+        assert!(http(StatusCode::TOO_MANY_REQUESTS).is_retriable());
+        assert!(gizwits(StatusCode::BAD_GATEWAY).is_retriable());
+
+        assert!(!http(StatusCode::BAD_REQUEST).is_retriable());
+        assert!(!http(StatusCode::NOT_FOUND).is_retriable());
+        assert!(!gizwits(StatusCode::UNAUTHORIZED).is_retriable());
+        assert!(!HeatzyError::NoToken.is_retriable());
+        assert!(!HeatzyError::InvalidValue("too hot".to_string()).is_retriable());
+    }
+}
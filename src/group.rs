@@ -0,0 +1,54 @@
+use crate::error::HeatzyError;
+use serde::{Deserialize, Serialize};
+
+/// Device group stored in the Gizwits cloud
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceGroup {
+    pub id: String,
+    #[serde(rename = "group_name")]
+    pub name: String,
+    /// Product of the member devices, when the group is restricted to one
+    pub product_key: Option<String>,
+    pub created_at: Option<String>,
+    /// Fields not modelled above
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Device belonging to a group
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroupDevice {
+    pub did: String,
+    pub dev_alias: Option<String>,
+    pub product_key: Option<String>,
+    /// Fields not modelled above
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Outcome of a control sent to one device
+#[derive(Debug)]
+pub struct ControlOutcome {
+    pub did: String,
+    pub result: Result<(), HeatzyError>,
+}
+
+/// Body of the group creation and rename requests
+#[derive(Debug, Serialize)]
+pub(crate) struct GroupRequest<'a> {
+    pub group_name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_key: Option<&'a str>,
+}
+
+/// Body of the member update requests
+#[derive(Debug, Serialize)]
+pub(crate) struct GroupDevicesRequest<'a> {
+    pub dids: &'a [&'a str],
+}
+
+/// Internal structure for parsing the id of a created group
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreatedGroupResponse {
+    pub id: String,
+}
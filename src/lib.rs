@@ -14,17 +14,53 @@
 //!     
 //!     let devices = client.list_devices().await?;
 //!     for device in devices {
-//!         println!("{}: {}", device.dev_alias, device.did);
+//!         println!("{}: {}", device.dev_alias.as_deref().unwrap_or("(no name)"), device.did);
 //!     }
 //!     
 //!     Ok(())
 //! }
 //! ```
+//!
+//! Use [`Client::builder`] to target another region or a custom endpoint:
+//!
+//! ```no_run
+//! use heatzy::{Client, Region};
+//! use std::time::Duration;
+//!
+//! # fn main() -> Result<(), heatzy::HeatzyError> {
+//! let client = Client::builder()
+//!     .region(Region::UnitedStates)
+//!     .timeout(Duration::from_secs(10))
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
 
+pub mod batch;
 pub mod client;
 pub mod error;
+pub mod group;
 pub mod models;
+pub mod product;
+pub mod retry;
+pub mod schedule;
+pub mod scheduler;
+pub mod sharing;
+pub mod state;
+pub mod token_store;
+mod watch;
+pub mod websocket;
 
-pub use client::Client;
-pub use error::HeatzyError;
-pub use models::{Device, DeviceMode, LoginCredentials, AuthResponse};
\ No newline at end of file
+pub use batch::BatchReport;
+pub use client::{Client, ClientBuilder, Region};
+pub use error::{GizwitsErrorCode, HeatzyError};
+pub use group::{ControlOutcome, DeviceGroup, GroupDevice};
+pub use models::{BindingReport, BoundDevice, ControlAttributes, Device, DeviceBinding, DeviceMode, ListDevicesOptions, LoginCredentials, AuthResponse, Session, UserInfo, UserUpdate};
+pub use product::{Capabilities, Product, ScheduleFormat, SetpointEncoding};
+pub use retry::RetryPolicy;
+pub use schedule::{ScheduleSlot, Weekday, WeeklySchedule, SLOTS_PER_DAY};
+pub use scheduler::{NewScheduledTask, Repeat, ScheduledTask, TaskAction, TaskTarget};
+pub use sharing::{Guest, ShareTarget, Sharing, SharingInvitation, SharingRole, SharingStatus};
+pub use state::{DerogationMode, DeviceState, Humidity, Sensors, Setpoints, Temperature};
+pub use token_store::{FileTokenStore, MemoryTokenStore, TokenKey, TokenStore};
+pub use websocket::{DeviceEvent, WebSocketSession, WebSocketSessionBuilder};
\ No newline at end of file
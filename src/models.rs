@@ -1,30 +1,170 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use crate::error::HeatzyError;
+use crate::product::Product;
 
 /// Login credentials
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LoginCredentials {
     pub username: String,
     pub password: String,
 }
 
 /// Authentication response
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthResponse {
     pub token: String,
     pub uid: String,
     pub expire_at: i64,
 }
 
+/// Account details
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub uid: String,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub name: Option<String>,
+    /// Preferred language, e.g. `en` or `fr`
+    pub lang: Option<String>,
+    pub remark: Option<String>,
+    pub is_anonymous: Option<bool>,
+    /// Fields not modelled above
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Account details to change, fields left to `None` are unchanged
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UserUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+}
+
+impl UserUpdate {
+    /// Check whether no field is changed
+    pub fn is_empty(&self) -> bool {
+        self.email.is_none() && self.phone.is_none() && self.name.is_none() && self.lang.is_none() && self.remark.is_none()
+    }
+}
+
+/// Body of the password change request
+#[derive(Debug, Serialize)]
+pub(crate) struct PasswordChange<'a> {
+    pub old_pwd: &'a str,
+    pub new_pwd: &'a str,
+}
+
+/// Body of the password reset request
+#[derive(Debug, Serialize)]
+pub(crate) struct PasswordReset<'a> {
+    pub email: &'a str,
+}
+
+/// Authenticated session held by the client
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub token: String,
+    /// User id, unknown when the token was set manually
+    pub uid: Option<String>,
+    /// Expiry as a Unix timestamp, unknown when the token was set manually
+    pub expire_at: Option<i64>,
+}
+
+impl Session {
+    /// Check whether the token expires within the given margin
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        match self.expire_at {
+            Some(expire_at) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
+                expire_at - (margin.as_secs() as i64) <= now
+            }
+            None => false,
+        }
+    }
+}
+
+impl From<AuthResponse> for Session {
+    fn from(auth_response: AuthResponse) -> Self {
+        Self {
+            token: auth_response.token,
+            uid: Some(auth_response.uid),
+            expire_at: Some(auth_response.expire_at),
+        }
+    }
+}
+
 /// Device information
-#[derive(Debug, Clone, Deserialize)]
+///
+/// The list endpoint returns every binding field while the single device
+/// endpoint omits some of them, hence the many optional fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     pub did: String,
     pub dev_alias: Option<String>,
+    pub product_key: Option<String>,
     pub product_name: String,
     pub mac: String,
     pub is_online: bool,
+    pub remark: Option<String>,
+    pub dev_label: Option<Vec<String>>,
+    /// M2M server host
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub port_s: Option<u16>,
+    pub ws_port: Option<u16>,
+    pub wss_port: Option<u16>,
+    pub passcode: Option<String>,
+    /// Binding role of the account: owner, guest or special
+    pub role: Option<String>,
+    /// Device type: normal, center_control or sub_dev
+    #[serde(rename = "type")]
+    pub device_type: Option<String>,
+    pub is_disabled: Option<bool>,
+    pub is_sandbox: Option<bool>,
+    pub wifi_soft_version: Option<String>,
+    pub wifi_hard_version: Option<String>,
+    pub mcu_soft_version: Option<String>,
+    pub mcu_hard_version: Option<String>,
+    /// Unix timestamp of the last state report
+    pub state_last_timestamp: Option<i64>,
+    /// Fields not modelled above
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Options for listing devices
+#[derive(Debug, Clone)]
+pub struct ListDevicesOptions {
+    /// Include devices disabled by the manufacturer
+    pub show_disabled: bool,
+    /// Only return devices of this product (filtered client-side)
+    pub product_key: Option<String>,
+    /// Number of devices requested per page; the server may return fewer
+    pub page_size: u32,
+}
+
+impl Default for ListDevicesOptions {
+    fn default() -> Self {
+        Self {
+            show_disabled: false,
+            product_key: None,
+            page_size: 100,
+        }
+    }
 }
 
 /// Internal structure for parsing device list response
@@ -36,12 +176,84 @@ pub(crate) struct DevicesResponse {
 /// Internal structure for parsing device data
 #[derive(Debug, Deserialize)]
 pub(crate) struct DeviceDataResponse {
-    pub attr: DeviceAttributes,
+    pub updated_at: Option<i64>,
+    pub attr: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize)]
-pub(crate) struct DeviceAttributes {
-    pub mode: serde_json::Value, // Can be string or number
+/// Device to bind to the account
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceBinding {
+    pub did: String,
+    /// Passcode proving access to the device
+    pub passcode: String,
+    #[serde(rename = "dev_alias", skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+}
+
+/// Per-device outcome of a bind or unbind request, or of a group membership change
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct BindingReport {
+    /// Devices whose binding or membership was changed
+    #[serde(default)]
+    pub success: Vec<String>,
+    /// Devices the API refused to change
+    #[serde(default)]
+    pub failed: Vec<String>,
+}
+
+impl BindingReport {
+    /// Check whether every device was changed
+    pub fn all_succeeded(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Device bound by its MAC address
+#[derive(Debug, Clone, Deserialize)]
+pub struct BoundDevice {
+    pub did: String,
+    pub mac: Option<String>,
+    pub product_key: Option<String>,
+    pub passcode: Option<String>,
+    pub is_online: Option<bool>,
+    /// Fields not modelled above
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Body of the bind request
+#[derive(Debug, Serialize)]
+pub(crate) struct BindRequest<'a> {
+    pub devices: &'a [DeviceBinding],
+}
+
+/// Body of the unbind request
+#[derive(Debug, Serialize)]
+pub(crate) struct UnbindRequest<'a> {
+    pub devices: Vec<UnbindDevice<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UnbindDevice<'a> {
+    pub did: &'a str,
+}
+
+/// Body of the binding update request, fields left to `None` are unchanged
+#[derive(Debug, Serialize)]
+pub(crate) struct BindingUpdate<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dev_alias: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remark: Option<&'a str>,
+}
+
+/// Body of the bind by MAC request
+#[derive(Debug, Serialize)]
+pub(crate) struct BindMacRequest<'a> {
+    pub product_key: &'a str,
+    pub mac: &'a str,
 }
 
 /// Device heating mode
@@ -70,12 +282,14 @@ impl DeviceMode {
     }
     
     /// Convert from API string value
+    ///
+    /// Accepts the Chinese strings reported by Pilote v1 devices as well.
     pub fn from_str_api(value: &str) -> Result<Self, HeatzyError> {
         match value {
-            "cft" => Ok(DeviceMode::Comfort),
-            "eco" => Ok(DeviceMode::Eco),
-            "fro" => Ok(DeviceMode::FrostProtection),
-            "stop" => Ok(DeviceMode::Stop),
+            "cft" | "舒适" => Ok(DeviceMode::Comfort),
+            "eco" | "经济" => Ok(DeviceMode::Eco),
+            "fro" | "解冻" => Ok(DeviceMode::FrostProtection),
+            "stop" | "停止" => Ok(DeviceMode::Stop),
             "cft1" => Ok(DeviceMode::ComfortMinus1),
             "cft2" => Ok(DeviceMode::ComfortMinus2),
             _ => Err(HeatzyError::InvalidMode(format!("Invalid mode string: {}", value))),
@@ -95,6 +309,30 @@ impl DeviceMode {
         }
     }
     
+    /// Convert from a raw API value, either a number or a string
+    pub fn from_api_value(value: &serde_json::Value) -> Result<Self, HeatzyError> {
+        if let Some(num) = value.as_i64() {
+            DeviceMode::from_int(num as i32)
+        } else if let Some(s) = value.as_str() {
+            DeviceMode::from_str_api(s)
+        } else {
+            Err(HeatzyError::Api(format!("Invalid mode value: {:?}", value)))
+        }
+    }
+    
+    /// Convert to the API value accepted by the given product
+    ///
+    /// Pilote v1 devices only understand their Chinese mode strings, newer
+    /// generations take the integer form.
+    pub fn to_api_value(&self, product: Product) -> Result<serde_json::Value, HeatzyError> {
+        match product {
+            Product::PiloteV1 => self.to_str_api_v1()
+                .map(serde_json::Value::from)
+                .ok_or_else(|| HeatzyError::Unsupported(format!("{} devices do not support the {} mode", product, self))),
+            _ => Ok(serde_json::Value::from(self.to_int())),
+        }
+    }
+    
     /// Convert to API integer value
     pub fn to_int(&self) -> i32 {
         match self {
@@ -119,6 +357,17 @@ impl DeviceMode {
         }
     }
     
+    /// Convert to the Chinese string used by Pilote v1 devices
+    pub fn to_str_api_v1(&self) -> Option<&'static str> {
+        match self {
+            DeviceMode::Comfort => Some("舒适"),
+            DeviceMode::Eco => Some("经济"),
+            DeviceMode::FrostProtection => Some("解冻"),
+            DeviceMode::Stop => Some("停止"),
+            DeviceMode::ComfortMinus1 | DeviceMode::ComfortMinus2 => None,
+        }
+    }
+    
     /// Get CLI-friendly string representation
     pub fn to_cli_str(&self) -> &'static str {
         match self {
@@ -138,13 +387,159 @@ impl fmt::Display for DeviceMode {
     }
 }
 
-/// Control attributes for setting device mode
-#[derive(Debug, Serialize)]
-pub(crate) struct ControlRequest {
-    pub attrs: ControlAttributes,
+/// Attributes written to a device by a control request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ControlAttributes(serde_json::Map<String, serde_json::Value>);
+
+impl ControlAttributes {
+    /// Create an empty set of attributes
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Add an attribute, builder style
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.insert(name, value);
+        self
+    }
+    
+    /// Add an attribute
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<serde_json::Value>) {
+        self.0.insert(name.into(), value.into());
+    }
+    
+    /// Get an attribute
+    pub fn get(&self, name: &str) -> Option<&serde_json::Value> {
+        self.0.get(name)
+    }
+    
+    /// Check whether no attribute is set
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    
+    /// Get the attributes as a JSON map
+    pub fn as_map(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.0
+    }
 }
 
+impl From<serde_json::Map<String, serde_json::Value>> for ControlAttributes {
+    fn from(attributes: serde_json::Map<String, serde_json::Value>) -> Self {
+        Self(attributes)
+    }
+}
+
+/// Control request writing device attributes
 #[derive(Debug, Serialize)]
-pub(crate) struct ControlAttributes {
-    pub mode: i32,
-}
\ No newline at end of file
+pub(crate) struct ControlRequest<'a> {
+    pub attrs: &'a ControlAttributes,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ALL_MODES: [DeviceMode; 6] = [
+        DeviceMode::Comfort,
+        DeviceMode::Eco,
+        DeviceMode::FrostProtection,
+        DeviceMode::Stop,
+        DeviceMode::ComfortMinus1,
+        DeviceMode::ComfortMinus2,
+    ];
+
+    const PRODUCTS: [Product; 8] = [
+        Product::PiloteV1,
+        Product::PiloteV2,
+        Product::PiloteV4,
+        Product::PilotePro,
+        Product::Glow,
+        Product::Onyx,
+        Product::Flam,
+        Product::Unknown,
+    ];
+
+    #[test]
+    fn modes_round_trip_for_every_product() {
+        for product in PRODUCTS {
+            for mode in product.capabilities().modes {
+                let value = mode.to_api_value(product).unwrap();
+                assert_eq!(DeviceMode::from_api_value(&value).unwrap(), *mode, "{} on {}", mode, product);
+            }
+        }
+    }
+
+    #[test]
+    fn pilote_v1_uses_chinese_strings() {
+        let cases = [
+            (DeviceMode::Comfort, "舒适"),
+            (DeviceMode::Eco, "经济"),
+            (DeviceMode::FrostProtection, "解冻"),
+            (DeviceMode::Stop, "停止"),
+        ];
+        for (mode, value) in cases {
+            assert_eq!(mode.to_api_value(Product::PiloteV1).unwrap(), json!(value));
+            assert_eq!(DeviceMode::from_api_value(&json!(value)).unwrap(), mode);
+        }
+
+        for mode in [DeviceMode::ComfortMinus1, DeviceMode::ComfortMinus2] {
+            assert!(matches!(mode.to_api_value(Product::PiloteV1), Err(HeatzyError::Unsupported(_))));
+        }
+    }
+
+    #[test]
+    fn newer_products_use_integers() {
+        let cases = [
+            (DeviceMode::Comfort, 0),
+            (DeviceMode::Eco, 1),
+            (DeviceMode::FrostProtection, 2),
+            (DeviceMode::Stop, 3),
+            (DeviceMode::ComfortMinus1, 4),
+            (DeviceMode::ComfortMinus2, 5),
+        ];
+        for (mode, value) in cases {
+            assert_eq!(mode.to_api_value(Product::PiloteV4).unwrap(), json!(value));
+            assert_eq!(DeviceMode::from_int(value).unwrap(), mode);
+        }
+        assert!(DeviceMode::from_int(6).is_err());
+    }
+
+    #[test]
+    fn string_and_cli_forms_round_trip() {
+        for mode in ALL_MODES {
+            assert_eq!(DeviceMode::from_str_api(mode.to_str_api()).unwrap(), mode);
+            assert_eq!(DeviceMode::from_api_value(&json!(mode.to_str_api())).unwrap(), mode);
+            assert_eq!(DeviceMode::from_cli_str(mode.to_cli_str()).unwrap(), mode);
+        }
+        assert!(DeviceMode::from_str_api("turbo").is_err());
+        assert!(DeviceMode::from_api_value(&json!(null)).is_err());
+    }
+
+    #[test]
+    fn device_fields_are_optional_and_unknown_keys_kept() {
+        let device: Device = serde_json::from_value(json!({
+            "did": "dev1",
+            "product_name": "Pilote_SoC",
+            "mac": "accf23aabbcc",
+            "is_online": true,
+            "type": "normal",
+            "state_last_timestamp": 1700000000,
+            "is_low_power": false,
+        })).unwrap();
+
+        assert_eq!(device.did, "dev1");
+        assert!(device.is_online);
+        assert_eq!(device.device_type.as_deref(), Some("normal"));
+        assert_eq!(device.state_last_timestamp, Some(1700000000));
+        assert_eq!(device.dev_alias, None);
+        assert_eq!(device.product_key, None);
+        assert_eq!(device.host, None);
+        assert_eq!(device.ws_port, None);
+        assert_eq!(device.role, None);
+        assert_eq!(device.is_disabled, None);
+        assert_eq!(device.extra.len(), 1);
+        assert_eq!(device.extra["is_low_power"], json!(false));
+    }
+}
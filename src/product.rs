@@ -0,0 +1,347 @@
+use crate::models::{Device, DeviceMode};
+use crate::state::Temperature;
+use std::fmt;
+
+const PILOTE_V1_PRODUCT_KEY: &str = "9420ae048da545c88fc6274d204dd25f";
+const PILOTE_V2_PRODUCT_KEY: &str = "51d16c22a5f74280bc3cfe9ebcdc6402";
+
+/// Setpoint range of the thermostat-capable products, in tenths of a degree
+const MIN_SETPOINT_TENTHS: i32 = 50;
+const MAX_SETPOINT_TENTHS: i32 = 300;
+
+/// Derogation times are stored in a single byte
+const MAX_DEROGATION_TIME: u32 = 255;
+
+const BASIC_MODES: &[DeviceMode] = &[
+    DeviceMode::Comfort,
+    DeviceMode::Eco,
+    DeviceMode::FrostProtection,
+    DeviceMode::Stop,
+];
+
+const ALL_MODES: &[DeviceMode] = &[
+    DeviceMode::Comfort,
+    DeviceMode::Eco,
+    DeviceMode::FrostProtection,
+    DeviceMode::Stop,
+    DeviceMode::ComfortMinus1,
+    DeviceMode::ComfortMinus2,
+];
+
+/// Heatzy product generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Product {
+    PiloteV1,
+    PiloteV2,
+    PiloteV4,
+    PilotePro,
+    Glow,
+    Onyx,
+    Flam,
+    Unknown,
+}
+
+impl Product {
+    /// Detect the product from its Gizwits product key, falling back to its name
+    pub fn detect(product_key: Option<&str>, product_name: &str) -> Self {
+        match product_key {
+            Some(PILOTE_V1_PRODUCT_KEY) => return Product::PiloteV1,
+            Some(PILOTE_V2_PRODUCT_KEY) => return Product::PiloteV2,
+            _ => {}
+        }
+
+        let name: String = product_name
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect();
+
+        if name.contains("glow") {
+            Product::Glow
+        } else if name.contains("onyx") {
+            Product::Onyx
+        } else if name.contains("flam") {
+            Product::Flam
+        } else if name.contains("pilotepro") {
+            Product::PilotePro
+        } else if name.contains("pilotesoc") || name.contains("pilote4") || name.contains("pilotev4") {
+            Product::PiloteV4
+        } else if name.contains("pilote2") || name.contains("pilotev2") {
+            Product::PiloteV2
+        } else if name == "pilote" || name.contains("pilotev1") {
+            Product::PiloteV1
+        } else {
+            Product::Unknown
+        }
+    }
+
+    /// Get the human-readable product name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Product::PiloteV1 => "Pilote v1",
+            Product::PiloteV2 => "Pilote v2",
+            Product::PiloteV4 => "Pilote v4",
+            Product::PilotePro => "Pilote Pro",
+            Product::Glow => "Glow",
+            Product::Onyx => "Onyx",
+            Product::Flam => "Flam",
+            Product::Unknown => "Unknown",
+        }
+    }
+
+    /// Name of the child lock attribute
+    pub fn lock_attribute(&self) -> &'static str {
+        match self {
+            Product::Glow => "LOCK_C",
+            _ => "lock_switch",
+        }
+    }
+
+    /// Get the features supported by this product
+    ///
+    /// Unknown products are assumed to support everything, leaving the API to
+    /// reject what they cannot do.
+    pub fn capabilities(&self) -> Capabilities {
+        let none = Capabilities {
+            modes: BASIC_MODES,
+            setpoints: None,
+            min_setpoint: Temperature::from_tenths(MIN_SETPOINT_TENTHS),
+            max_setpoint: Temperature::from_tenths(MAX_SETPOINT_TENTHS),
+            temperature_sensor: false,
+            humidity_sensor: false,
+            boost: false,
+            max_boost_minutes: 0,
+            vacation: false,
+            max_vacation_days: 0,
+            presence_detection: false,
+            child_lock: false,
+            window_detection: false,
+            schedule: None,
+        };
+
+        match self {
+            Product::PiloteV1 => none,
+            Product::PiloteV2 => Capabilities {
+                child_lock: true,
+                schedule: Some(ScheduleFormat::HalfHourSlots),
+                ..none
+            },
+            Product::PiloteV4 => Capabilities {
+                modes: ALL_MODES,
+                boost: true,
+                max_boost_minutes: MAX_DEROGATION_TIME,
+                vacation: true,
+                max_vacation_days: MAX_DEROGATION_TIME,
+                presence_detection: true,
+                child_lock: true,
+                window_detection: true,
+                schedule: Some(ScheduleFormat::HalfHourSlots),
+                ..none
+            },
+            Product::PilotePro => Capabilities {
+                modes: ALL_MODES,
+                setpoints: Some(SetpointEncoding::Tenths),
+                min_setpoint: Temperature::from_tenths(MIN_SETPOINT_TENTHS),
+                max_setpoint: Temperature::from_tenths(MAX_SETPOINT_TENTHS),
+                temperature_sensor: true,
+                humidity_sensor: true,
+                boost: true,
+                max_boost_minutes: MAX_DEROGATION_TIME,
+                vacation: true,
+                max_vacation_days: MAX_DEROGATION_TIME,
+                presence_detection: true,
+                child_lock: true,
+                window_detection: true,
+                schedule: Some(ScheduleFormat::HalfHourSlots),
+            },
+            Product::Glow => Capabilities {
+                setpoints: Some(SetpointEncoding::SplitBytes),
+                temperature_sensor: true,
+                boost: true,
+                max_boost_minutes: MAX_DEROGATION_TIME,
+                vacation: true,
+                max_vacation_days: MAX_DEROGATION_TIME,
+                child_lock: true,
+                window_detection: true,
+                schedule: Some(ScheduleFormat::HalfHourSlots),
+                ..none
+            },
+            Product::Onyx | Product::Flam => Capabilities {
+                setpoints: Some(SetpointEncoding::Tenths),
+                temperature_sensor: true,
+                boost: true,
+                max_boost_minutes: MAX_DEROGATION_TIME,
+                vacation: true,
+                max_vacation_days: MAX_DEROGATION_TIME,
+                child_lock: true,
+                window_detection: true,
+                schedule: Some(ScheduleFormat::HalfHourSlots),
+                ..none
+            },
+            Product::Unknown => Capabilities {
+                modes: ALL_MODES,
+                setpoints: Some(SetpointEncoding::Tenths),
+                min_setpoint: Temperature::from_tenths(MIN_SETPOINT_TENTHS),
+                max_setpoint: Temperature::from_tenths(MAX_SETPOINT_TENTHS),
+                temperature_sensor: true,
+                humidity_sensor: true,
+                boost: true,
+                max_boost_minutes: MAX_DEROGATION_TIME,
+                vacation: true,
+                max_vacation_days: MAX_DEROGATION_TIME,
+                presence_detection: true,
+                child_lock: true,
+                window_detection: true,
+                schedule: Some(ScheduleFormat::HalfHourSlots),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Product {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Encoding of the temperature setpoint attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetpointEncoding {
+    /// Tenths of a degree split over high and low bytes (`cft_tempH`/`cft_tempL`)
+    SplitBytes,
+    /// Tenths of a degree in a single attribute (`cft_temp`)
+    Tenths,
+}
+
+/// Format of the weekly programme stored on the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleFormat {
+    /// 48 half-hour slots per day packed in `p1_data1`..`p7_data12`
+    HalfHourSlots,
+}
+
+/// Features supported by a product
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub modes: &'static [DeviceMode],
+    pub setpoints: Option<SetpointEncoding>,
+    /// Lowest setpoint accepted
+    pub min_setpoint: Temperature,
+    /// Highest setpoint accepted
+    pub max_setpoint: Temperature,
+    pub temperature_sensor: bool,
+    pub humidity_sensor: bool,
+    pub boost: bool,
+    /// Longest boost accepted, in minutes
+    pub max_boost_minutes: u32,
+    pub vacation: bool,
+    /// Longest vacation accepted, in days
+    pub max_vacation_days: u32,
+    pub presence_detection: bool,
+    pub child_lock: bool,
+    pub window_detection: bool,
+    pub schedule: Option<ScheduleFormat>,
+}
+
+impl Capabilities {
+    /// Check whether the mode can be set
+    pub fn supports_mode(&self, mode: DeviceMode) -> bool {
+        self.modes.contains(&mode)
+    }
+
+    /// Check whether the temperature is an accepted setpoint
+    pub fn supports_setpoint(&self, temperature: Temperature) -> bool {
+        self.setpoints.is_some() && (self.min_setpoint..=self.max_setpoint).contains(&temperature)
+    }
+}
+
+impl Device {
+    /// Detect the product generation of this device
+    pub fn product(&self) -> Product {
+        Product::detect(self.product_key.as_deref(), &self.product_name)
+    }
+
+    /// Get the features supported by this device
+    pub fn capabilities(&self) -> Capabilities {
+        self.product().capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_products_by_key() {
+        let cases = [
+            (PILOTE_V1_PRODUCT_KEY, Product::PiloteV1),
+            (PILOTE_V2_PRODUCT_KEY, Product::PiloteV2),
+        ];
+        for (product_key, expected) in cases {
+            // The key wins over a misleading name
+            assert_eq!(Product::detect(Some(product_key), "Glow"), expected, "{}", product_key);
+        }
+    }
+
+    #[test]
+    fn detects_products_by_name() {
+        let cases = [
+            ("Pilote", Product::PiloteV1),
+            ("Pilote V1", Product::PiloteV1),
+            ("Pilote2", Product::PiloteV2),
+            ("Pilote_V2", Product::PiloteV2),
+            ("Pilote_SoC", Product::PiloteV4),
+            ("Pilote4", Product::PiloteV4),
+            ("Pilote V4", Product::PiloteV4),
+            ("Pilote Pro", Product::PilotePro),
+            ("PILOTE_PRO", Product::PilotePro),
+            ("Glow", Product::Glow),
+            ("INEA GLOW", Product::Glow),
+            ("Onyx", Product::Onyx),
+            ("Flam", Product::Flam),
+            ("Flam_Week2", Product::Flam),
+            ("Heatzy Shine", Product::Unknown),
+            ("Pilote Heater", Product::Unknown),
+            ("", Product::Unknown),
+        ];
+        for (product_name, expected) in cases {
+            assert_eq!(Product::detect(Some("unknown-key"), product_name), expected, "{}", product_name);
+            assert_eq!(Product::detect(None, product_name), expected, "{}", product_name);
+        }
+    }
+
+    #[test]
+    fn pilote_v1_only_supports_basic_modes() {
+        let capabilities = Product::PiloteV1.capabilities();
+        assert!(capabilities.supports_mode(DeviceMode::Eco));
+        assert!(!capabilities.supports_mode(DeviceMode::ComfortMinus1));
+        assert!(!capabilities.supports_mode(DeviceMode::ComfortMinus2));
+        assert!(capabilities.setpoints.is_none());
+    }
+
+    #[test]
+    fn setpoints_are_checked_against_the_product_range() {
+        let capabilities = Product::PilotePro.capabilities();
+        assert!(capabilities.supports_setpoint(Temperature::from_tenths(50)));
+        assert!(capabilities.supports_setpoint(Temperature::from_tenths(300)));
+        assert!(!capabilities.supports_setpoint(Temperature::from_tenths(301)));
+        assert!(!Product::PiloteV2.capabilities().supports_setpoint(Temperature::from_tenths(200)));
+    }
+
+    #[test]
+    fn only_glow_names_its_lock_differently() {
+        let cases = [
+            (Product::PiloteV1, "lock_switch"),
+            (Product::PiloteV2, "lock_switch"),
+            (Product::PiloteV4, "lock_switch"),
+            (Product::PilotePro, "lock_switch"),
+            (Product::Glow, "LOCK_C"),
+            (Product::Onyx, "lock_switch"),
+            (Product::Flam, "lock_switch"),
+            (Product::Unknown, "lock_switch"),
+        ];
+        for (product, expected) in cases {
+            assert_eq!(product.lock_attribute(), expected, "{}", product);
+        }
+    }
+}
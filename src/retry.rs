@@ -0,0 +1,265 @@
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Policy deciding when and how often failed requests are sent again
+///
+/// Requests that may not be safely repeated, such as creating a binding, are
+/// only retried when the server cannot have processed them: on connection
+/// failures and `429 Too Many Requests` responses.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound for the computed delay
+    pub max_backoff: Duration,
+    /// Factor applied to the delay after each attempt
+    pub multiplier: f64,
+    /// Random spread applied to each delay, as a fraction of it (0.0 to 1.0)
+    pub jitter: f64,
+    /// Retry when the connection could not be established
+    pub retry_on_connect: bool,
+    /// Retry when the request timed out
+    pub retry_on_timeout: bool,
+    /// Retry when the connection dropped before the response was received
+    pub retry_on_disconnect: bool,
+    /// Response statuses that are retried
+    pub retry_statuses: Vec<StatusCode>,
+    /// Wait for the delay given by the `Retry-After` header when present
+    pub respect_retry_after: bool,
+    /// Upper bound for the delay given by the `Retry-After` header
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+            retry_on_connect: true,
+            retry_on_timeout: true,
+            retry_on_disconnect: true,
+            retry_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            respect_retry_after: true,
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before the given retry (1 for the first retry), jitter included
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let base = base.min(self.max_backoff.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (fastrand::f64() * 2.0 - 1.0);
+        Duration::from_secs_f64((base * factor).max(0.0))
+    }
+
+    /// Check whether a transport error is retried
+    pub(crate) fn retries_error(&self, error: &reqwest::Error, idempotent: bool) -> bool {
+        if error.is_connect() {
+            return self.retry_on_connect;
+        }
+        if error.is_timeout() {
+            return idempotent && self.retry_on_timeout;
+        }
+        // The server may have processed the request before the connection dropped
+        idempotent && (error.is_request() || error.is_body()) && self.retry_on_disconnect
+    }
+
+    /// Check whether a response status is retried
+    pub(crate) fn retries_status(&self, status: StatusCode, idempotent: bool) -> bool {
+        (idempotent || status == StatusCode::TOO_MANY_REQUESTS) && self.retry_statuses.contains(&status)
+    }
+
+    /// Delay before retrying after the given response
+    pub(crate) fn response_delay(&self, retry: u32, response: &reqwest::Response) -> Duration {
+        self.delay_for_headers(retry, response.headers())
+    }
+
+    /// Delay before retrying after a response with these headers
+    fn delay_for_headers(&self, retry: u32, headers: &HeaderMap) -> Duration {
+        let backoff = self.backoff(retry);
+        if !self.respect_retry_after {
+            return backoff;
+        }
+
+        // Only the delay-seconds form is supported, HTTP dates are ignored
+        headers.get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(|seconds| Duration::from_secs(seconds).min(self.max_retry_after).max(backoff))
+            .unwrap_or(backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn deterministic() -> RetryPolicy {
+        RetryPolicy {
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn retry_after(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_cap() {
+        let policy = deterministic();
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(6), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn jitter_stays_within_its_spread() {
+        let policy = RetryPolicy {
+            jitter: 0.2,
+            ..RetryPolicy::default()
+        };
+
+        for _ in 0..100 {
+            let delay = policy.backoff(2);
+            assert!(delay >= Duration::from_millis(800) && delay <= Duration::from_millis(1200), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn retry_after_seconds_extend_the_backoff() {
+        let policy = deterministic();
+
+        assert_eq!(policy.delay_for_headers(1, &retry_after("3")), Duration::from_secs(3));
+        // Never shorter than the backoff
+        assert_eq!(policy.delay_for_headers(3, &retry_after("1")), Duration::from_secs(2));
+        assert_eq!(policy.delay_for_headers(1, &HeaderMap::new()), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        let policy = deterministic();
+        assert_eq!(policy.delay_for_headers(1, &retry_after("86400")), Duration::from_secs(60));
+        assert_eq!(policy.delay_for_headers(1, &retry_after("18446744073709551615")), Duration::from_secs(60));
+
+        let policy = RetryPolicy {
+            max_retry_after: Duration::from_secs(5),
+            ..deterministic()
+        };
+        assert_eq!(policy.delay_for_headers(1, &retry_after("30")), Duration::from_secs(5));
+        // The backoff still applies when larger than the cap
+        assert_eq!(policy.delay_for_headers(6, &retry_after("30")), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn retry_after_dates_and_disabled_header_fall_back_to_backoff() {
+        let policy = deterministic();
+        assert_eq!(
+            policy.delay_for_headers(1, &retry_after("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Duration::from_millis(500)
+        );
+
+        let policy = RetryPolicy {
+            respect_retry_after: false,
+            ..deterministic()
+        };
+        assert_eq!(policy.delay_for_headers(1, &retry_after("30")), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn server_errors_are_only_retried_for_idempotent_requests() {
+        let policy = RetryPolicy::default();
+
+        assert!(policy.retries_status(StatusCode::SERVICE_UNAVAILABLE, true));
+        assert!(!policy.retries_status(StatusCode::SERVICE_UNAVAILABLE, false));
+        assert!(!policy.retries_status(StatusCode::INTERNAL_SERVER_ERROR, false));
+        // The server did not process a throttled request
+        assert!(policy.retries_status(StatusCode::TOO_MANY_REQUESTS, false));
+        assert!(!policy.retries_status(StatusCode::BAD_REQUEST, true));
+        assert!(!RetryPolicy { retry_statuses: Vec::new(), ..policy }.retries_status(StatusCode::TOO_MANY_REQUESTS, true));
+    }
+
+    #[tokio::test]
+    async fn connection_failures_are_retried_for_any_request() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let error = reqwest::get(format!("http://{}", address)).await.unwrap_err();
+        assert!(error.is_connect());
+
+        let policy = RetryPolicy::default();
+        assert!(policy.retries_error(&error, true));
+        assert!(policy.retries_error(&error, false));
+        assert!(!RetryPolicy { retry_on_connect: false, ..policy }.retries_error(&error, true));
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_only_retried_for_idempotent_requests() {
+        // Accepts connections at the socket level but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        let error = client.get(format!("http://{}", listener.local_addr().unwrap())).send().await.unwrap_err();
+        assert!(error.is_timeout());
+
+        let policy = RetryPolicy::default();
+        assert!(policy.retries_error(&error, true));
+        assert!(!policy.retries_error(&error, false));
+        assert!(!RetryPolicy { retry_on_timeout: false, ..policy }.retries_error(&error, true));
+    }
+
+    #[tokio::test]
+    async fn dropped_connections_are_only_retried_for_idempotent_requests() {
+        // Reads the request, then closes the connection without answering
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            use tokio::io::AsyncReadExt;
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = stream.read(&mut [0u8; 1024]).await;
+        });
+
+        let error = reqwest::get(format!("http://{}", address)).await.unwrap_err();
+        assert!(!error.is_connect() && !error.is_timeout(), "{:?}", error);
+
+        let policy = RetryPolicy::default();
+        assert!(policy.retries_error(&error, true));
+        assert!(!policy.retries_error(&error, false));
+        assert!(!RetryPolicy { retry_on_disconnect: false, ..policy }.retries_error(&error, true));
+    }
+}
@@ -0,0 +1,796 @@
+use crate::error::HeatzyError;
+use crate::models::ControlAttributes;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of half-hour slots in a day
+pub const SLOTS_PER_DAY: usize = 48;
+
+/// Each programme attribute byte packs four 2-bit slots
+const SLOTS_PER_BYTE: usize = 4;
+const BYTES_PER_DAY: usize = SLOTS_PER_DAY / SLOTS_PER_BYTE;
+
+/// Day of the week
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// All days, starting on Monday
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// Position in the week, 0 for Monday
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// Convert from CLI string value
+    pub fn from_cli_str(value: &str) -> Result<Self, HeatzyError> {
+        match value.to_lowercase().as_str() {
+            "mon" | "monday" => Ok(Weekday::Monday),
+            "tue" | "tuesday" => Ok(Weekday::Tuesday),
+            "wed" | "wednesday" => Ok(Weekday::Wednesday),
+            "thu" | "thursday" => Ok(Weekday::Thursday),
+            "fri" | "friday" => Ok(Weekday::Friday),
+            "sat" | "saturday" => Ok(Weekday::Saturday),
+            "sun" | "sunday" => Ok(Weekday::Sunday),
+            _ => Err(HeatzyError::InvalidValue(format!("Invalid day: {}", value))),
+        }
+    }
+
+    /// Get CLI-friendly string representation
+    pub fn to_cli_str(&self) -> &'static str {
+        match self {
+            Weekday::Monday => "mon",
+            Weekday::Tuesday => "tue",
+            Weekday::Wednesday => "wed",
+            Weekday::Thursday => "thu",
+            Weekday::Friday => "fri",
+            Weekday::Saturday => "sat",
+            Weekday::Sunday => "sun",
+        }
+    }
+}
+
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_cli_str())
+    }
+}
+
+/// Setting of a half-hour slot of the programme
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScheduleSlot {
+    Comfort,         // 0b00
+    Eco,             // 0b01
+    FrostProtection, // 0b10
+    Off,             // 0b11
+}
+
+impl ScheduleSlot {
+    /// All settings, in device value order
+    pub const ALL: [ScheduleSlot; 4] = [
+        ScheduleSlot::Comfort,
+        ScheduleSlot::Eco,
+        ScheduleSlot::FrostProtection,
+        ScheduleSlot::Off,
+    ];
+
+    /// Convert from the 2-bit device value
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => ScheduleSlot::Comfort,
+            1 => ScheduleSlot::Eco,
+            2 => ScheduleSlot::FrostProtection,
+            _ => ScheduleSlot::Off,
+        }
+    }
+
+    /// Convert to the 2-bit device value
+    pub fn to_bits(&self) -> u8 {
+        match self {
+            ScheduleSlot::Comfort => 0,
+            ScheduleSlot::Eco => 1,
+            ScheduleSlot::FrostProtection => 2,
+            ScheduleSlot::Off => 3,
+        }
+    }
+
+    /// Convert from CLI string value
+    pub fn from_cli_str(value: &str) -> Result<Self, HeatzyError> {
+        match value.to_lowercase().as_str() {
+            "comfort" => Ok(ScheduleSlot::Comfort),
+            "eco" => Ok(ScheduleSlot::Eco),
+            "frost-protection" | "frost" => Ok(ScheduleSlot::FrostProtection),
+            "off" | "stop" => Ok(ScheduleSlot::Off),
+            _ => Err(HeatzyError::InvalidValue(format!("Invalid slot: {}. Valid slots are: comfort, eco, frost-protection, off", value))),
+        }
+    }
+
+    /// Get CLI-friendly string representation
+    pub fn to_cli_str(&self) -> &'static str {
+        match self {
+            ScheduleSlot::Comfort => "comfort",
+            ScheduleSlot::Eco => "eco",
+            ScheduleSlot::FrostProtection => "frost-protection",
+            ScheduleSlot::Off => "off",
+        }
+    }
+}
+
+impl fmt::Display for ScheduleSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_cli_str())
+    }
+}
+
+/// Weekly programme of a Pilote device: 7 days of 48 half-hour slots
+///
+/// On the device each day is stored in twelve byte attributes, `p1_data1` to
+/// `p1_data12` for Monday up to `p7_data12` for Sunday. Each byte holds four
+/// consecutive slots, the earliest one in the two most significant bits.
+///
+/// The programme can also be written as text, one line per group of days
+/// listing periods followed by the setting of the rest of the day:
+///
+/// ```text
+/// # Working days
+/// mon-fri 06:30-08:30 comfort; 18:00-22:00 comfort; default eco
+/// sat,sun 09:00-12:00 comfort; 18:00-23:00 comfort; default eco
+/// ```
+///
+/// Parse it with [`str::parse`] and print it with [`fmt::Display`]. With serde
+/// it is represented as a map from day names to the same description.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "ScheduleDocument", try_from = "ScheduleDocument")]
+pub struct WeeklySchedule {
+    days: [[ScheduleSlot; SLOTS_PER_DAY]; 7],
+}
+
+impl WeeklySchedule {
+    /// Create a programme using the same setting all week
+    pub fn new(slot: ScheduleSlot) -> Self {
+        Self {
+            days: [[slot; SLOTS_PER_DAY]; 7],
+        }
+    }
+
+    /// Get the slots of a day, the first one starting at midnight
+    pub fn day(&self, day: Weekday) -> &[ScheduleSlot; SLOTS_PER_DAY] {
+        &self.days[day.index()]
+    }
+
+    /// Get the setting of a slot
+    ///
+    /// Panics if `slot` is not below [`SLOTS_PER_DAY`].
+    pub fn slot(&self, day: Weekday, slot: usize) -> ScheduleSlot {
+        self.days[day.index()][slot]
+    }
+
+    /// Change the setting of a slot
+    ///
+    /// Panics if `slot` is not below [`SLOTS_PER_DAY`].
+    pub fn set_slot(&mut self, day: Weekday, slot: usize, setting: ScheduleSlot) {
+        self.days[day.index()][slot] = setting;
+    }
+
+    /// Change the setting of the slots from `start` (included) to `end` (excluded)
+    ///
+    /// Panics if `end` is above [`SLOTS_PER_DAY`].
+    pub fn set_range(&mut self, day: Weekday, start: usize, end: usize, setting: ScheduleSlot) {
+        for slot in &mut self.days[day.index()][start..end] {
+            *slot = setting;
+        }
+    }
+
+    /// Decode the programme from device attributes
+    pub fn from_attributes(attributes: &Map<String, Value>) -> Result<Self, HeatzyError> {
+        let mut schedule = Self::new(ScheduleSlot::Comfort);
+
+        for day in Weekday::ALL {
+            for byte_index in 0..BYTES_PER_DAY {
+                let name = attribute_name(day, byte_index);
+                let byte = attributes.get(&name)
+                    .and_then(Value::as_u64)
+                    .filter(|value| *value <= u8::MAX as u64)
+                    .ok_or_else(|| HeatzyError::Api(format!("Missing or invalid programme attribute {}", name)))?;
+                schedule.set_byte(day, byte_index, byte as u8);
+            }
+        }
+
+        Ok(schedule)
+    }
+
+    /// Encode the programme as device attributes
+    pub fn to_attributes(&self) -> ControlAttributes {
+        let mut attributes = ControlAttributes::new();
+        for day in Weekday::ALL {
+            for byte_index in 0..BYTES_PER_DAY {
+                attributes.insert(attribute_name(day, byte_index), self.byte(day, byte_index));
+            }
+        }
+        attributes
+    }
+
+    /// Pack four consecutive slots into a byte, the earliest in the high bits
+    fn byte(&self, day: Weekday, byte_index: usize) -> u8 {
+        let slots = &self.days[day.index()][byte_index * SLOTS_PER_BYTE..(byte_index + 1) * SLOTS_PER_BYTE];
+        slots.iter().fold(0, |byte, slot| (byte << 2) | slot.to_bits())
+    }
+
+    /// Unpack a byte into four consecutive slots
+    fn set_byte(&mut self, day: Weekday, byte_index: usize, byte: u8) {
+        for offset in 0..SLOTS_PER_BYTE {
+            let shift = 2 * (SLOTS_PER_BYTE - 1 - offset);
+            self.days[day.index()][byte_index * SLOTS_PER_BYTE + offset] = ScheduleSlot::from_bits(byte >> shift);
+        }
+    }
+}
+
+impl fmt::Display for WeeklySchedule {
+    /// Write the programme as text, grouping the days programmed alike
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let layouts: Vec<DayLayout> = Weekday::ALL.iter()
+            .map(|day| DayLayout::of(self.day(*day)))
+            .collect();
+        let mut written = [false; 7];
+
+        for first in 0..layouts.len() {
+            if written[first] {
+                continue;
+            }
+            let days: Vec<usize> = (first..layouts.len())
+                .filter(|index| layouts[*index] == layouts[first])
+                .collect();
+            for index in &days {
+                written[*index] = true;
+            }
+
+            write!(f, "{}", format_days(&days))?;
+            for period in &layouts[first].periods {
+                write!(f, " {}-{} {};", format_time(period.start), format_time(period.end), period.setting)?;
+            }
+            writeln!(f, " default {}", layouts[first].default)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for WeeklySchedule {
+    type Err = HeatzyError;
+
+    /// Parse the text form of a programme, every day must be programmed once
+    fn from_str(text: &str) -> Result<Self, HeatzyError> {
+        let mut schedule = Self::new(ScheduleSlot::Comfort);
+        let mut programmed_on: [Option<usize>; 7] = [None; 7];
+        let mut line_count = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            line_count = line_number;
+            let syntax_error = |column: usize, message: String| HeatzyError::ScheduleSyntax {
+                line: line_number,
+                column,
+                message,
+            };
+
+            let content = line.split('#').next().unwrap_or_default();
+            let tokens = tokenize(content);
+            let Some((days_token, entries)) = tokens.split_first() else {
+                continue;
+            };
+
+            let days = parse_days(days_token).map_err(|(column, message)| syntax_error(column, message))?;
+            let slots = parse_day_entries(days_token, entries).map_err(|(column, message)| syntax_error(column, message))?;
+
+            for day in days {
+                if let Some(previous) = programmed_on[day.index()] {
+                    return Err(syntax_error(
+                        days_token.column,
+                        format!("{} is already programmed on line {}", day, previous),
+                    ));
+                }
+                programmed_on[day.index()] = Some(line_number);
+                schedule.days[day.index()] = slots;
+            }
+        }
+
+        let missing: Vec<&str> = Weekday::ALL.iter()
+            .filter(|day| programmed_on[day.index()].is_none())
+            .map(Weekday::to_cli_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(HeatzyError::ScheduleSyntax {
+                line: line_count + 1,
+                column: 1,
+                message: format!("Missing programme for {}", missing.join(", ")),
+            });
+        }
+
+        Ok(schedule)
+    }
+}
+
+/// Run of slots from `start` (included) to `end` (excluded) with the same setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Period {
+    start: usize,
+    end: usize,
+    setting: ScheduleSlot,
+}
+
+/// A day described as a default setting overridden by periods
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DayLayout {
+    default: ScheduleSlot,
+    periods: Vec<Period>,
+}
+
+impl DayLayout {
+    /// Describe a day, using its most common setting as default
+    fn of(slots: &[ScheduleSlot; SLOTS_PER_DAY]) -> Self {
+        let default = ScheduleSlot::ALL.into_iter()
+            .max_by_key(|setting| (slots.iter().filter(|slot| *slot == setting).count(), Reverse(setting.to_bits())))
+            .unwrap_or(ScheduleSlot::Comfort);
+
+        let mut periods = Vec::new();
+        let mut start = 0;
+        while start < SLOTS_PER_DAY {
+            let setting = slots[start];
+            let end = (start..SLOTS_PER_DAY)
+                .find(|index| slots[*index] != setting)
+                .unwrap_or(SLOTS_PER_DAY);
+            if setting != default {
+                periods.push(Period { start, end, setting });
+            }
+            start = end;
+        }
+
+        Self { default, periods }
+    }
+
+    /// Add a period, returning the one it overlaps if any
+    fn add_period(&mut self, period: Period) -> Result<(), Period> {
+        if let Some(other) = self.periods.iter().find(|other| period.start < other.end && other.start < period.end) {
+            return Err(*other);
+        }
+        self.periods.push(period);
+        Ok(())
+    }
+
+    /// Expand the description into slots
+    fn slots(&self) -> [ScheduleSlot; SLOTS_PER_DAY] {
+        let mut slots = [self.default; SLOTS_PER_DAY];
+        for period in &self.periods {
+            slots[period.start..period.end].fill(period.setting);
+        }
+        slots
+    }
+}
+
+/// Word of a programme line, with its 1-based column
+struct Token<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+/// Split a line into words, `;` being a word of its own
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start: Option<(usize, usize)> = None;
+
+    for (column, (offset, c)) in line.char_indices().enumerate() {
+        if c.is_whitespace() || c == ';' {
+            if let Some((start_offset, start_column)) = start.take() {
+                tokens.push(Token { text: &line[start_offset..offset], column: start_column + 1 });
+            }
+            if c == ';' {
+                tokens.push(Token { text: ";", column: column + 1 });
+            }
+        } else if start.is_none() {
+            start = Some((offset, column));
+        }
+    }
+    if let Some((start_offset, start_column)) = start {
+        tokens.push(Token { text: &line[start_offset..], column: start_column + 1 });
+    }
+
+    tokens
+}
+
+/// Parse a list of days such as `mon-fri`, `sat,sun` or `all`
+fn parse_days(token: &Token<'_>) -> Result<Vec<Weekday>, (usize, String)> {
+    if matches!(token.text.to_lowercase().as_str(), "all" | "daily") {
+        return Ok(Weekday::ALL.to_vec());
+    }
+
+    let mut days = Vec::new();
+    let mut offset = 0;
+    for part in token.text.split(',') {
+        let column = token.column + token.text[..offset].chars().count();
+        offset += part.len() + 1;
+
+        let parse_day = |name: &str| Weekday::from_cli_str(name)
+            .map_err(|_| (column, format!("Invalid day '{}', expected mon, tue, wed, thu, fri, sat, sun or all", name)));
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_day(first)?, parse_day(last)?);
+                if last < first {
+                    return Err((column, format!("Day range {} ends before it starts", part)));
+                }
+                days.extend(&Weekday::ALL[first.index()..=last.index()]);
+            }
+            None => days.push(parse_day(part)?),
+        }
+    }
+
+    Ok(days)
+}
+
+/// Parse the `;`-separated periods and default following the days of a line
+fn parse_day_entries(days_token: &Token<'_>, tokens: &[Token<'_>]) -> Result<[ScheduleSlot; SLOTS_PER_DAY], (usize, String)> {
+    let mut layout = DayLayout { default: ScheduleSlot::Comfort, periods: Vec::new() };
+    let mut default = None;
+
+    for entry in tokens.split(|token| token.text == ";") {
+        match entry {
+            [] => continue,
+            [keyword, setting] if keyword.text.eq_ignore_ascii_case("default") => {
+                if default.is_some() {
+                    return Err((keyword.column, "Default setting given twice".to_string()));
+                }
+                default = Some(parse_setting(setting)?);
+            }
+            [range, setting] => {
+                let (start, end) = parse_range(range)?;
+                let period = Period { start, end, setting: parse_setting(setting)? };
+                layout.add_period(period).map_err(|other| (range.column, format!(
+                    "Period overlaps {}-{}", format_time(other.start), format_time(other.end)
+                )))?;
+            }
+            [single] => {
+                return Err((single.column + single.text.chars().count(), format!("Expected a setting after '{}'", single.text)));
+            }
+            [_, _, extra, ..] => {
+                return Err((extra.column, format!("Unexpected '{}', periods must be separated by ';'", extra.text)));
+            }
+        }
+    }
+
+    let covered: usize = layout.periods.iter().map(|period| period.end - period.start).sum();
+    match default {
+        Some(default) => layout.default = default,
+        None if covered == SLOTS_PER_DAY => {}
+        None => return Err((days_token.column, "Missing default setting for the rest of the day".to_string())),
+    }
+
+    Ok(layout.slots())
+}
+
+/// Parse a setting such as `comfort` or `eco`
+fn parse_setting(token: &Token<'_>) -> Result<ScheduleSlot, (usize, String)> {
+    ScheduleSlot::from_cli_str(token.text).map_err(|_| (token.column, format!(
+        "Invalid setting '{}', expected comfort, eco, frost-protection or off", token.text
+    )))
+}
+
+/// Parse a period such as `06:30-08:30` into slot indices
+fn parse_range(token: &Token<'_>) -> Result<(usize, usize), (usize, String)> {
+    let Some((start, end)) = token.text.split_once('-') else {
+        return Err((token.column, format!("Expected a period such as 06:30-08:30, got '{}'", token.text)));
+    };
+    let end_column = token.column + start.chars().count() + 1;
+
+    let start_slot = parse_time(start).map_err(|message| (token.column, message))?;
+    let end_slot = parse_time(end).map_err(|message| (end_column, message))?;
+    if end_slot <= start_slot {
+        return Err((end_column, format!("Period {} must end after it starts", token.text)));
+    }
+
+    Ok((start_slot, end_slot))
+}
+
+/// Parse a time on a half hour, from `00:00` to `24:00`, into a slot index
+fn parse_time(text: &str) -> Result<usize, String> {
+    let invalid = || format!("Invalid time '{}', expected HH:MM on a half hour", text);
+
+    let (hours, minutes) = text.split_once(':').ok_or_else(invalid)?;
+    let hours: usize = hours.parse().map_err(|_| invalid())?;
+    let minutes: usize = minutes.parse().map_err(|_| invalid())?;
+    if hours > 24 || (minutes != 0 && minutes != 30) {
+        return Err(invalid());
+    }
+
+    hours.checked_mul(2)
+        .map(|slot| slot + minutes / 30)
+        .filter(|slot| *slot <= SLOTS_PER_DAY)
+        .ok_or_else(invalid)
+}
+
+/// Format a slot index as a time, `24:00` for the end of the day
+fn format_time(slot: usize) -> String {
+    format!("{:02}:{:02}", slot / 2, (slot % 2) * 30)
+}
+
+/// Format day indices compactly, e.g. `mon-wed,fri`
+fn format_days(days: &[usize]) -> String {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start < days.len() {
+        let mut end = start;
+        while end + 1 < days.len() && days[end + 1] == days[end] + 1 {
+            end += 1;
+        }
+
+        let (first, last) = (Weekday::ALL[days[start]], Weekday::ALL[days[end]]);
+        match end - start {
+            0 => parts.push(first.to_string()),
+            1 => parts.push(format!("{},{}", first, last)),
+            _ => parts.push(format!("{}-{}", first, last)),
+        }
+        start = end + 1;
+    }
+    parts.join(",")
+}
+
+/// Serde form of a programme: each day with its default and periods
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct ScheduleDocument(BTreeMap<Weekday, DayDocument>);
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DayDocument {
+    default: ScheduleSlot,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    periods: Vec<PeriodDocument>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PeriodDocument {
+    start: String,
+    end: String,
+    setting: ScheduleSlot,
+}
+
+impl From<WeeklySchedule> for ScheduleDocument {
+    fn from(schedule: WeeklySchedule) -> Self {
+        let days = Weekday::ALL.into_iter()
+            .map(|day| {
+                let layout = DayLayout::of(schedule.day(day));
+                let periods = layout.periods.iter()
+                    .map(|period| PeriodDocument {
+                        start: format_time(period.start),
+                        end: format_time(period.end),
+                        setting: period.setting,
+                    })
+                    .collect();
+                (day, DayDocument { default: layout.default, periods })
+            })
+            .collect();
+        Self(days)
+    }
+}
+
+impl TryFrom<ScheduleDocument> for WeeklySchedule {
+    type Error = HeatzyError;
+
+    fn try_from(document: ScheduleDocument) -> Result<Self, HeatzyError> {
+        let mut schedule = Self::new(ScheduleSlot::Comfort);
+
+        for day in Weekday::ALL {
+            let Some(day_document) = document.0.get(&day) else {
+                return Err(HeatzyError::InvalidValue(format!("Missing programme for {:?}", day)));
+            };
+
+            let mut layout = DayLayout { default: day_document.default, periods: Vec::new() };
+            for period in &day_document.periods {
+                let start = parse_time(&period.start).map_err(HeatzyError::InvalidValue)?;
+                let end = parse_time(&period.end).map_err(HeatzyError::InvalidValue)?;
+                if end <= start {
+                    return Err(HeatzyError::InvalidValue(format!(
+                        "Period {}-{} on {:?} must end after it starts", period.start, period.end, day
+                    )));
+                }
+                layout.add_period(Period { start, end, setting: period.setting }).map_err(|other| {
+                    HeatzyError::InvalidValue(format!(
+                        "Period {}-{} on {:?} overlaps {}-{}",
+                        period.start, period.end, day, format_time(other.start), format_time(other.end)
+                    ))
+                })?;
+            }
+            schedule.days[day.index()] = layout.slots();
+        }
+
+        Ok(schedule)
+    }
+}
+
+/// Name of the attribute holding a byte of a day, e.g. `p1_data1`
+fn attribute_name(day: Weekday, byte_index: usize) -> String {
+    format!("p{}_data{}", day.index() + 1, byte_index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_earliest_slot_in_high_bits() {
+        let mut schedule = WeeklySchedule::new(ScheduleSlot::Comfort);
+        schedule.set_slot(Weekday::Monday, 0, ScheduleSlot::Off);
+        schedule.set_slot(Weekday::Monday, 1, ScheduleSlot::FrostProtection);
+        schedule.set_slot(Weekday::Monday, 2, ScheduleSlot::Eco);
+
+        let attributes = schedule.to_attributes();
+        assert_eq!(attributes.get("p1_data1"), Some(&Value::from(0b11_10_01_00)));
+        assert_eq!(attributes.get("p1_data2"), Some(&Value::from(0)));
+    }
+
+    #[test]
+    fn maps_days_and_bytes_to_attribute_names() {
+        let mut schedule = WeeklySchedule::new(ScheduleSlot::Comfort);
+        schedule.set_slot(Weekday::Sunday, SLOTS_PER_DAY - 1, ScheduleSlot::Eco);
+
+        let attributes = schedule.to_attributes();
+        assert_eq!(attributes.as_map().len(), 7 * BYTES_PER_DAY);
+        assert_eq!(attributes.get("p7_data12"), Some(&Value::from(0b00_00_00_01)));
+        assert_eq!(attributes.get("p6_data12"), Some(&Value::from(0)));
+    }
+
+    #[test]
+    fn decodes_bytes_into_slots() {
+        let mut attributes = WeeklySchedule::new(ScheduleSlot::Comfort).to_attributes().as_map().clone();
+        attributes.insert("p3_data5".to_string(), Value::from(0b01_01_10_11));
+
+        let schedule = WeeklySchedule::from_attributes(&attributes).unwrap();
+        assert_eq!(schedule.slot(Weekday::Wednesday, 16), ScheduleSlot::Eco);
+        assert_eq!(schedule.slot(Weekday::Wednesday, 17), ScheduleSlot::Eco);
+        assert_eq!(schedule.slot(Weekday::Wednesday, 18), ScheduleSlot::FrostProtection);
+        assert_eq!(schedule.slot(Weekday::Wednesday, 19), ScheduleSlot::Off);
+        assert_eq!(schedule.slot(Weekday::Wednesday, 20), ScheduleSlot::Comfort);
+    }
+
+    #[test]
+    fn round_trips_through_attributes() {
+        let mut schedule = WeeklySchedule::new(ScheduleSlot::Eco);
+        for (i, day) in Weekday::ALL.into_iter().enumerate() {
+            schedule.set_range(day, 12 + i, 17 + 2 * i, ScheduleSlot::Comfort);
+            schedule.set_range(day, 36, 44 - i, ScheduleSlot::FrostProtection);
+            schedule.set_slot(day, SLOTS_PER_DAY - 1 - i, ScheduleSlot::Off);
+        }
+
+        let attributes = schedule.to_attributes();
+        assert_eq!(WeeklySchedule::from_attributes(attributes.as_map()).unwrap(), schedule);
+    }
+
+    #[test]
+    fn rejects_missing_or_invalid_attributes() {
+        let mut attributes = WeeklySchedule::new(ScheduleSlot::Comfort).to_attributes().as_map().clone();
+        attributes.remove("p4_data7");
+        assert!(WeeklySchedule::from_attributes(&attributes).is_err());
+
+        attributes.insert("p4_data7".to_string(), Value::from(256));
+        assert!(WeeklySchedule::from_attributes(&attributes).is_err());
+    }
+
+    const WORKING_WEEK: &str = "\
+# Working days
+mon-fri 06:30-08:30 comfort; 18:00-22:00 comfort; default eco
+sat,sun 09:00-12:00 comfort; 18:00-23:00 comfort; default eco
+";
+
+    #[test]
+    fn parses_text_programme() {
+        let schedule: WeeklySchedule = WORKING_WEEK.parse().unwrap();
+
+        assert_eq!(schedule.slot(Weekday::Monday, 12), ScheduleSlot::Eco);
+        assert_eq!(schedule.slot(Weekday::Monday, 13), ScheduleSlot::Comfort);
+        assert_eq!(schedule.slot(Weekday::Friday, 43), ScheduleSlot::Comfort);
+        assert_eq!(schedule.slot(Weekday::Friday, 44), ScheduleSlot::Eco);
+        assert_eq!(schedule.slot(Weekday::Sunday, 16), ScheduleSlot::Eco);
+        assert_eq!(schedule.slot(Weekday::Sunday, 18), ScheduleSlot::Comfort);
+        assert_eq!(schedule.slot(Weekday::Sunday, 46), ScheduleSlot::Eco);
+    }
+
+    #[test]
+    fn prints_text_programme() {
+        let schedule: WeeklySchedule = WORKING_WEEK.parse().unwrap();
+        let expected = "\
+mon-fri 06:30-08:30 comfort; 18:00-22:00 comfort; default eco
+sat,sun 09:00-12:00 comfort; 18:00-23:00 comfort; default eco
+";
+        assert_eq!(schedule.to_string(), expected);
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut schedule = WeeklySchedule::new(ScheduleSlot::Eco);
+        schedule.set_range(Weekday::Monday, 0, SLOTS_PER_DAY, ScheduleSlot::Off);
+        schedule.set_range(Weekday::Wednesday, 0, 3, ScheduleSlot::FrostProtection);
+        schedule.set_range(Weekday::Wednesday, 40, SLOTS_PER_DAY, ScheduleSlot::Comfort);
+        schedule.set_range(Weekday::Friday, 0, 3, ScheduleSlot::FrostProtection);
+        schedule.set_range(Weekday::Friday, 40, SLOTS_PER_DAY, ScheduleSlot::Comfort);
+
+        let text = schedule.to_string();
+        assert_eq!(text.parse::<WeeklySchedule>().unwrap(), schedule);
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let error = "all default eco\nmon 06:30-08:15 comfort; default eco".parse::<WeeklySchedule>().unwrap_err();
+        assert!(matches!(error, HeatzyError::ScheduleSyntax { line: 2, column: 11, .. }), "{:?}", error);
+
+        let error = "mon-fri 06:30-08:30 comfy; default eco".parse::<WeeklySchedule>().unwrap_err();
+        assert!(matches!(error, HeatzyError::ScheduleSyntax { line: 1, column: 21, .. }), "{:?}", error);
+
+        let error = "mon-fri default eco\nfri-sun default eco".parse::<WeeklySchedule>().unwrap_err();
+        assert!(matches!(error, HeatzyError::ScheduleSyntax { line: 2, column: 1, .. }), "{:?}", error);
+
+        let error = "mon-fri default eco".parse::<WeeklySchedule>().unwrap_err();
+        assert!(matches!(error, HeatzyError::ScheduleSyntax { line: 2, column: 1, .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let schedule: WeeklySchedule = WORKING_WEEK.parse().unwrap();
+
+        let json = serde_json::to_value(&schedule).unwrap();
+        assert_eq!(json["monday"]["default"], "eco");
+        assert_eq!(json["monday"]["periods"][0]["start"], "06:30");
+        assert_eq!(serde_json::from_value::<WeeklySchedule>(json).unwrap(), schedule);
+    }
+
+    #[test]
+    fn round_trips_through_yaml() {
+        let schedule: WeeklySchedule = WORKING_WEEK.parse().unwrap();
+
+        let yaml = serde_yaml::to_string(&schedule).unwrap();
+        assert!(yaml.contains("saturday:"), "{}", yaml);
+        assert_eq!(serde_yaml::from_str::<WeeklySchedule>(&yaml).unwrap(), schedule);
+    }
+
+    #[test]
+    fn rejects_out_of_range_times() {
+        assert_eq!(parse_time("24:00"), Ok(SLOTS_PER_DAY));
+        assert!(parse_time("24:30").is_err());
+        assert!(parse_time("25:00").is_err());
+        assert!(parse_time(&format!("{}:00", usize::MAX)).is_err());
+    }
+
+    #[test]
+    fn document_periods_must_not_overlap() {
+        let schedule: WeeklySchedule = WORKING_WEEK.parse().unwrap();
+        let mut json = serde_json::to_value(&schedule).unwrap();
+        json["monday"]["periods"][1]["start"] = "08:00".into();
+
+        let error = serde_json::from_value::<WeeklySchedule>(json).unwrap_err();
+        assert!(error.to_string().contains("overlaps 06:30-08:30"), "{}", error);
+
+        let error = "all 06:30-08:30 comfort; 08:00-09:00 eco; default eco".parse::<WeeklySchedule>().unwrap_err();
+        assert!(matches!(error, HeatzyError::ScheduleSyntax { line: 1, column: 26, .. }), "{:?}", error);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::error::HeatzyError;
+use crate::models::{ControlAttributes, DeviceMode};
+use crate::schedule::Weekday;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// When a scheduler task fires
+///
+/// Dates and times are interpreted in UTC by the Gizwits cloud.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Repeat {
+    /// Only on the given date (`YYYY-MM-DD`)
+    Once(String),
+    /// Every week on the given days
+    Weekly(Vec<Weekday>),
+    /// Every month on the given days (1 to 31)
+    Monthly(Vec<u8>),
+}
+
+impl Repeat {
+    /// Repeat every day of the week
+    pub fn daily() -> Self {
+        Repeat::Weekly(Weekday::ALL.to_vec())
+    }
+
+    /// Check the dates and days are well formed
+    fn validate(&self) -> Result<(), HeatzyError> {
+        match self {
+            Repeat::Once(date) => validate_date(date),
+            Repeat::Weekly(days) if days.is_empty() => {
+                Err(HeatzyError::InvalidValue("Weekly repeat needs at least one day".to_string()))
+            }
+            Repeat::Monthly(days) if days.is_empty() => {
+                Err(HeatzyError::InvalidValue("Monthly repeat needs at least one day".to_string()))
+            }
+            Repeat::Monthly(days) => match days.iter().find(|day| !(1..=31).contains(*day)) {
+                Some(day) => Err(HeatzyError::InvalidValue(format!("Invalid day of the month: {}", day))),
+                None => Ok(()),
+            },
+            Repeat::Weekly(_) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Repeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Repeat::Once(date) => write!(f, "once on {}", date),
+            Repeat::Weekly(days) if days.len() == Weekday::ALL.len() => write!(f, "daily"),
+            Repeat::Weekly(days) => {
+                let days: Vec<&str> = days.iter().map(Weekday::to_cli_str).collect();
+                write!(f, "weekly on {}", days.join(","))
+            }
+            Repeat::Monthly(days) => {
+                let days: Vec<String> = days.iter().map(u8::to_string).collect();
+                write!(f, "monthly on day {}", days.join(","))
+            }
+        }
+    }
+}
+
+/// What a scheduler task does to its device
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskAction {
+    /// Switch to a heating mode, encoded for the device product
+    Mode(DeviceMode),
+    /// Write arbitrary attributes
+    Attributes(ControlAttributes),
+}
+
+/// Scheduler task to create, or replacing an existing one
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewScheduledTask {
+    /// Time of day (`HH:MM`, UTC)
+    pub time: String,
+    pub repeat: Repeat,
+    pub action: TaskAction,
+    pub enabled: bool,
+    pub remark: Option<String>,
+}
+
+impl NewScheduledTask {
+    /// Create an enabled task
+    pub fn new(time: impl Into<String>, repeat: Repeat, action: TaskAction) -> Self {
+        Self {
+            time: time.into(),
+            repeat,
+            action,
+            enabled: true,
+            remark: None,
+        }
+    }
+
+    /// Build the request body, `attrs` being the encoded action
+    pub(crate) fn to_request(&self, device_id: &str, attrs: ControlAttributes) -> Result<SchedulerRequest, HeatzyError> {
+        validate_time(&self.time)?;
+        self.repeat.validate()?;
+
+        let (date, repeat, days) = match &self.repeat {
+            Repeat::Once(date) => (Some(date.clone()), "none".to_string(), Vec::new()),
+            Repeat::Weekly(days) => {
+                let days: Vec<&str> = days.iter().map(Weekday::to_cli_str).collect();
+                (None, days.join(","), Vec::new())
+            }
+            Repeat::Monthly(days) => (None, "day".to_string(), days.clone()),
+        };
+
+        Ok(SchedulerRequest {
+            date,
+            time: self.time.clone(),
+            repeat,
+            days,
+            enabled: self.enabled,
+            remark: self.remark.clone(),
+            task: vec![TaskTarget {
+                did: device_id.to_string(),
+                attrs,
+            }],
+        })
+    }
+}
+
+/// Device and attributes written by a scheduler task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskTarget {
+    pub did: String,
+    pub attrs: ControlAttributes,
+}
+
+/// Scheduler task stored in the Gizwits cloud
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledTask {
+    pub id: String,
+    /// Time of day (`HH:MM`, UTC)
+    pub time: String,
+    pub repeat: Repeat,
+    pub enabled: bool,
+    pub remark: Option<String>,
+    /// Devices written when the task fires
+    pub targets: Vec<TaskTarget>,
+    pub created_at: Option<String>,
+}
+
+impl ScheduledTask {
+    /// Check whether the task writes to the given device
+    pub fn targets_device(&self, device_id: &str) -> bool {
+        self.targets.iter().any(|target| target.did == device_id)
+    }
+}
+
+impl TryFrom<SchedulerResponse> for ScheduledTask {
+    type Error = HeatzyError;
+
+    fn try_from(response: SchedulerResponse) -> Result<Self, HeatzyError> {
+        let repeat = match response.repeat.as_str() {
+            "none" => Repeat::Once(response.date.unwrap_or_default()),
+            "day" => Repeat::Monthly(response.days),
+            days => Repeat::Weekly(
+                days.split(',')
+                    .map(|day| Weekday::from_cli_str(day.trim()))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| HeatzyError::Api(format!("Invalid scheduler repeat: {}", days)))?,
+            ),
+        };
+
+        Ok(Self {
+            id: response.id,
+            time: response.time,
+            repeat,
+            enabled: response.enabled,
+            remark: response.remark.filter(|remark| !remark.is_empty()),
+            targets: response.task,
+            created_at: response.created_at,
+        })
+    }
+}
+
+/// Body of the scheduler create and update requests
+#[derive(Debug, Serialize)]
+pub(crate) struct SchedulerRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    pub time: String,
+    pub repeat: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<u8>,
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+    pub task: Vec<TaskTarget>,
+}
+
+/// Internal structure for parsing scheduler tasks
+#[derive(Debug, Deserialize)]
+pub(crate) struct SchedulerResponse {
+    pub id: String,
+    pub date: Option<String>,
+    pub time: String,
+    pub repeat: String,
+    #[serde(default)]
+    pub days: Vec<u8>,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    pub remark: Option<String>,
+    #[serde(default)]
+    pub task: Vec<TaskTarget>,
+    pub created_at: Option<String>,
+}
+
+/// Internal structure for parsing the id of a created task
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreatedResponse {
+    pub id: String,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Check a `HH:MM` time of day
+fn validate_time(time: &str) -> Result<(), HeatzyError> {
+    let valid = time.split_once(':')
+        .filter(|(hours, minutes)| hours.len() == 2 && minutes.len() == 2)
+        .and_then(|(hours, minutes)| Some((hours.parse::<u8>().ok()?, minutes.parse::<u8>().ok()?)))
+        .is_some_and(|(hours, minutes)| hours < 24 && minutes < 60);
+
+    if valid {
+        Ok(())
+    } else {
+        Err(HeatzyError::InvalidValue(format!("Invalid time '{}', expected HH:MM", time)))
+    }
+}
+
+/// Check a `YYYY-MM-DD` date
+fn validate_date(date: &str) -> Result<(), HeatzyError> {
+    let parts: Vec<&str> = date.split('-').collect();
+    let valid = matches!(parts.as_slice(), [year, month, day]
+        if year.len() == 4 && month.len() == 2 && day.len() == 2
+            && year.parse::<u16>().is_ok()
+            && month.parse::<u8>().is_ok_and(|month| (1..=12).contains(&month))
+            && day.parse::<u8>().is_ok_and(|day| (1..=31).contains(&day)));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(HeatzyError::InvalidValue(format!("Invalid date '{}', expected YYYY-MM-DD", date)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Send a task through the request body and back as the server would list it
+    fn round_trip(task: &NewScheduledTask) -> ScheduledTask {
+        let attrs = ControlAttributes::new().with("mode", 1);
+        let mut body = serde_json::to_value(task.to_request("dev1", attrs).unwrap()).unwrap();
+        body["id"] = json!("task1");
+        let response: SchedulerResponse = serde_json::from_value(body).unwrap();
+        ScheduledTask::try_from(response).unwrap()
+    }
+
+    fn task(repeat: Repeat) -> NewScheduledTask {
+        NewScheduledTask::new("06:30", repeat, TaskAction::Mode(DeviceMode::Comfort))
+    }
+
+    #[test]
+    fn repeats_survive_a_round_trip() {
+        let repeats = [
+            Repeat::Once("2024-03-01".to_string()),
+            Repeat::Weekly(vec![Weekday::Monday, Weekday::Friday]),
+            Repeat::daily(),
+            Repeat::Monthly(vec![1, 15, 31]),
+        ];
+        for repeat in repeats {
+            let listed = round_trip(&task(repeat.clone()));
+            assert_eq!(listed.repeat, repeat);
+            assert_eq!(listed.time, "06:30");
+            assert!(listed.enabled);
+        }
+    }
+
+    #[test]
+    fn targets_and_remark_survive_a_round_trip() {
+        let mut new_task = task(Repeat::daily());
+        new_task.enabled = false;
+        new_task.remark = Some("Morning".to_string());
+
+        let listed = round_trip(&new_task);
+
+        assert_eq!(listed.id, "task1");
+        assert!(!listed.enabled);
+        assert_eq!(listed.remark.as_deref(), Some("Morning"));
+        assert_eq!(listed.targets, vec![TaskTarget {
+            did: "dev1".to_string(),
+            attrs: ControlAttributes::new().with("mode", 1),
+        }]);
+    }
+
+    #[test]
+    fn invalid_tasks_are_not_sent() {
+        let attrs = ControlAttributes::new;
+        assert!(task(Repeat::Once("01/03/2024".to_string())).to_request("dev1", attrs()).is_err());
+        assert!(task(Repeat::Weekly(Vec::new())).to_request("dev1", attrs()).is_err());
+        assert!(task(Repeat::Monthly(vec![32])).to_request("dev1", attrs()).is_err());
+        assert!(NewScheduledTask::new("24:00", Repeat::daily(), TaskAction::Attributes(attrs()))
+            .to_request("dev1", attrs())
+            .is_err());
+    }
+
+    #[test]
+    fn unknown_repeats_are_an_error() {
+        let response: SchedulerResponse = serde_json::from_value(json!({
+            "id": "task1",
+            "time": "06:30",
+            "repeat": "week",
+        })).unwrap();
+
+        assert!(ScheduledTask::try_from(response).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+
+/// Who a device is shared with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareTarget {
+    /// Account with this user id
+    Uid(String),
+    /// Account with this username
+    Username(String),
+    /// Account with this email address
+    Email(String),
+    /// Account with this phone number
+    Phone(String),
+    /// Anyone scanning the returned QR code within the given number of minutes
+    QrCode { duration_minutes: u32 },
+}
+
+/// Side of the sharings to list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharingRole {
+    /// Invitations sent for devices owned by the account
+    Owner,
+    /// Invitations received by the account
+    Guest,
+}
+
+impl SharingRole {
+    /// Convert to API integer value
+    pub fn to_int(&self) -> u8 {
+        match self {
+            SharingRole::Owner => 0,
+            SharingRole::Guest => 1,
+        }
+    }
+}
+
+/// State of a sharing invitation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharingStatus {
+    Pending,   // 0
+    Accepted,  // 1
+    Refused,   // 2
+    Cancelled, // 3
+    Other(i64),
+}
+
+impl SharingStatus {
+    /// Convert from API integer value
+    pub fn from_int(value: i64) -> Self {
+        match value {
+            0 => SharingStatus::Pending,
+            1 => SharingStatus::Accepted,
+            2 => SharingStatus::Refused,
+            3 => SharingStatus::Cancelled,
+            other => SharingStatus::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for SharingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SharingStatus::Pending => write!(f, "pending"),
+            SharingStatus::Accepted => write!(f, "accepted"),
+            SharingStatus::Refused => write!(f, "refused"),
+            SharingStatus::Cancelled => write!(f, "cancelled"),
+            SharingStatus::Other(value) => write!(f, "status {}", value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SharingStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(SharingStatus::from_int(i64::deserialize(deserializer)?))
+    }
+}
+
+/// Sharing invitation for a device
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sharing {
+    #[serde(deserialize_with = "deserialize_id")]
+    pub id: String,
+    pub did: String,
+    pub status: SharingStatus,
+    pub dev_alias: Option<String>,
+    pub product_name: Option<String>,
+    /// Account the device is shared with, when known
+    pub uid: Option<String>,
+    pub user_name: Option<String>,
+    /// Account owning the device
+    pub owner_uid: Option<String>,
+    pub expired_at: Option<String>,
+    pub created_at: Option<String>,
+    /// Fields not modelled above
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Created sharing invitation
+#[derive(Debug, Clone, Deserialize)]
+pub struct SharingInvitation {
+    #[serde(deserialize_with = "deserialize_id")]
+    pub id: String,
+    /// Content of the QR code to scan, for QR code sharings
+    pub qr_content: Option<String>,
+}
+
+/// Account with access to a shared device
+#[derive(Debug, Clone, Deserialize)]
+pub struct Guest {
+    pub uid: String,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    /// Id of the sharing that gave access
+    #[serde(default, deserialize_with = "deserialize_optional_id")]
+    pub sharing_id: Option<String>,
+    pub created_at: Option<String>,
+    /// Fields not modelled above
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Body of the sharing creation request
+#[derive(Debug, Serialize)]
+pub(crate) struct SharingRequest<'a> {
+    #[serde(rename = "type")]
+    pub sharing_type: u8,
+    pub did: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uid: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+}
+
+impl<'a> SharingRequest<'a> {
+    /// Build the request sharing a device with a target
+    pub fn new(device_id: &'a str, target: &'a ShareTarget) -> Self {
+        let mut request = Self {
+            sharing_type: 0,
+            did: device_id,
+            uid: None,
+            username: None,
+            email: None,
+            phone: None,
+            duration: None,
+        };
+        match target {
+            ShareTarget::Uid(uid) => request.uid = Some(uid),
+            ShareTarget::Username(username) => request.username = Some(username),
+            ShareTarget::Email(email) => request.email = Some(email),
+            ShareTarget::Phone(phone) => request.phone = Some(phone),
+            ShareTarget::QrCode { duration_minutes } => {
+                request.sharing_type = 1;
+                request.duration = Some(*duration_minutes);
+            }
+        }
+        request
+    }
+}
+
+/// Internal structure for parsing the sharing list
+#[derive(Debug, Deserialize)]
+pub(crate) struct SharingsResponse {
+    pub objects: Vec<Sharing>,
+}
+
+/// Internal structure for parsing the guest list
+#[derive(Debug, Deserialize)]
+pub(crate) struct GuestsResponse {
+    pub users: Vec<Guest>,
+}
+
+/// Accept ids sent either as numbers or as strings
+fn deserialize_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(id) => Ok(id),
+        serde_json::Value::Number(id) => Ok(id.to_string()),
+        other => Err(serde::de::Error::custom(format!("invalid id: {}", other))),
+    }
+}
+
+fn deserialize_optional_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(id)) => Ok(Some(id)),
+        Some(serde_json::Value::Number(id)) => Ok(Some(id.to_string())),
+        Some(other) => Err(serde::de::Error::custom(format!("invalid id: {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn ids_may_be_numbers_or_strings() {
+        let numeric: SharingInvitation = serde_json::from_value(json!({"id": 42})).unwrap();
+        let text: SharingInvitation = serde_json::from_value(json!({"id": "42", "qr_content": "code"})).unwrap();
+
+        assert_eq!(numeric.id, "42");
+        assert_eq!(text.id, "42");
+        assert_eq!(text.qr_content.as_deref(), Some("code"));
+        assert!(serde_json::from_value::<SharingInvitation>(json!({"id": true})).is_err());
+    }
+
+    #[test]
+    fn optional_ids_may_be_missing_null_or_numbers() {
+        let guest = |sharing_id: serde_json::Value| -> Guest {
+            serde_json::from_value(json!({"uid": "uid", "sharing_id": sharing_id})).unwrap()
+        };
+
+        assert_eq!(guest(json!(7)).sharing_id.as_deref(), Some("7"));
+        assert_eq!(guest(json!("7")).sharing_id.as_deref(), Some("7"));
+        assert_eq!(guest(json!(null)).sharing_id, None);
+        let missing: Guest = serde_json::from_value(json!({"uid": "uid"})).unwrap();
+        assert_eq!(missing.sharing_id, None);
+    }
+
+    #[test]
+    fn statuses_decode_from_integers() {
+        let sharing: Sharing = serde_json::from_value(json!({"id": 1, "did": "dev1", "status": 1, "team_id": 3})).unwrap();
+
+        assert_eq!(sharing.status, SharingStatus::Accepted);
+        assert_eq!(sharing.extra["team_id"], json!(3));
+        assert_eq!(SharingStatus::from_int(9), SharingStatus::Other(9));
+    }
+
+    #[test]
+    fn requests_carry_the_target_field() {
+        let request = |target: ShareTarget| serde_json::to_value(SharingRequest::new("dev1", &target)).unwrap();
+
+        assert_eq!(request(ShareTarget::Uid("u1".to_string())), json!({"type": 0, "did": "dev1", "uid": "u1"}));
+        assert_eq!(request(ShareTarget::Username("bob".to_string())), json!({"type": 0, "did": "dev1", "username": "bob"}));
+        assert_eq!(
+            request(ShareTarget::Email("bob@example.com".to_string())),
+            json!({"type": 0, "did": "dev1", "email": "bob@example.com"})
+        );
+        assert_eq!(request(ShareTarget::Phone("+33600000000".to_string())), json!({"type": 0, "did": "dev1", "phone": "+33600000000"}));
+        assert_eq!(request(ShareTarget::QrCode { duration_minutes: 30 }), json!({"type": 1, "did": "dev1", "duration": 30}));
+    }
+}
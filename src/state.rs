@@ -0,0 +1,385 @@
+use crate::error::HeatzyError;
+use crate::models::{ControlAttributes, DeviceMode};
+use crate::product::SetpointEncoding;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Temperature, stored in tenths of a degree Celsius like on the devices
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Temperature {
+    tenths: i32,
+}
+
+impl Temperature {
+    /// Create from tenths of a degree Celsius
+    pub fn from_tenths(tenths: i32) -> Self {
+        Self { tenths }
+    }
+
+    /// Create from degrees Celsius, rounded to the nearest tenth
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self {
+            tenths: (celsius * 10.0).round() as i32,
+        }
+    }
+
+    /// Get the value in tenths of a degree Celsius
+    pub fn tenths(&self) -> i32 {
+        self.tenths
+    }
+
+    /// Get the value in degrees Celsius
+    pub fn celsius(&self) -> f32 {
+        self.tenths as f32 / 10.0
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} °C", self.celsius())
+    }
+}
+
+/// Relative humidity in percent
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Humidity(f32);
+
+impl Humidity {
+    /// Create from a percentage
+    pub fn from_percent(percent: f32) -> Self {
+        Self(percent)
+    }
+
+    /// Get the value in percent
+    pub fn percent(&self) -> f32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Humidity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0} %", self.0)
+    }
+}
+
+/// Ambient readings of a device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensors {
+    pub temperature: Option<Temperature>,
+    pub humidity: Option<Humidity>,
+    /// Unix timestamp of the report the readings come from
+    pub sampled_at: Option<i64>,
+}
+
+/// Comfort and eco target temperatures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Setpoints {
+    pub comfort: Option<Temperature>,
+    pub eco: Option<Temperature>,
+}
+
+/// Derogation overriding the current mode or programme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DerogationMode {
+    Off,      // 0
+    Vacation, // 1
+    Boost,    // 2
+    Presence, // 3
+}
+
+impl DerogationMode {
+    /// Convert from API integer value
+    pub fn from_int(value: i64) -> Result<Self, HeatzyError> {
+        match value {
+            0 => Ok(DerogationMode::Off),
+            1 => Ok(DerogationMode::Vacation),
+            2 => Ok(DerogationMode::Boost),
+            3 => Ok(DerogationMode::Presence),
+            _ => Err(HeatzyError::Api(format!("Invalid derogation mode: {}", value))),
+        }
+    }
+
+    /// Convert to API integer value
+    pub fn to_int(&self) -> i64 {
+        match self {
+            DerogationMode::Off => 0,
+            DerogationMode::Vacation => 1,
+            DerogationMode::Boost => 2,
+            DerogationMode::Presence => 3,
+        }
+    }
+}
+
+impl fmt::Display for DerogationMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DerogationMode::Off => "off",
+            DerogationMode::Vacation => "vacation",
+            DerogationMode::Boost => "boost",
+            DerogationMode::Presence => "presence",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Snapshot of the latest attributes reported by a device
+///
+/// Fields are `None` when the device generation does not report them, or
+/// reports a value unknown to this version or out of range (the raw value stays
+/// in `attributes`).
+#[derive(Debug, Clone)]
+pub struct DeviceState {
+    pub did: String,
+    pub mode: Option<DeviceMode>,
+    pub derogation_mode: Option<DerogationMode>,
+    /// Remaining derogation time: minutes for boost, days for vacation
+    pub derogation_time: Option<u32>,
+    /// Whether the weekly programme is running (`timer_switch`)
+    pub program_enabled: Option<bool>,
+    pub lock_enabled: Option<bool>,
+    pub window_detection_enabled: Option<bool>,
+    pub temperature: Option<Temperature>,
+    pub humidity: Option<Humidity>,
+    pub comfort_setpoint: Option<Temperature>,
+    pub eco_setpoint: Option<Temperature>,
+    /// Unix timestamp of the report
+    pub updated_at: Option<i64>,
+    /// Every attribute as reported, including those not decoded above
+    pub attributes: Map<String, Value>,
+}
+
+impl DeviceState {
+    /// Decode the attributes reported by a device
+    pub fn from_attributes(did: &str, attributes: Map<String, Value>, updated_at: Option<i64>) -> Result<Self, HeatzyError> {
+        // Values unknown to this version, e.g. from newer firmware, are left out
+        // rather than failing the whole snapshot
+        let mode = attributes.get("mode")
+            .and_then(|value| DeviceMode::from_api_value(value)
+                .map_err(|e| warn!("Ignoring mode of {}: {}", did, e))
+                .ok());
+        let derogation_mode = int_attribute(&attributes, "derog_mode")
+            .and_then(|value| DerogationMode::from_int(value)
+                .map_err(|e| warn!("Ignoring derogation mode of {}: {}", did, e))
+                .ok());
+        let derogation_time = int_attribute(&attributes, "derog_time")
+            .and_then(|time| u32::try_from(time)
+                .map_err(|_| warn!("Ignoring derogation time of {}: {}", did, time))
+                .ok());
+
+        Ok(Self {
+            did: did.to_string(),
+            mode,
+            derogation_mode,
+            derogation_time,
+            program_enabled: bool_attribute(&attributes, "timer_switch"),
+            lock_enabled: bool_attribute(&attributes, "lock_switch")
+                .or_else(|| bool_attribute(&attributes, "LOCK_C")),
+            window_detection_enabled: bool_attribute(&attributes, "window_switch"),
+            temperature: temperature_attribute(&attributes, "cur_temp"),
+            humidity: attributes.get("cur_humi")
+                .and_then(Value::as_f64)
+                .map(|h| Humidity::from_percent(h as f32)),
+            comfort_setpoint: temperature_attribute(&attributes, "cft_temp"),
+            eco_setpoint: temperature_attribute(&attributes, "eco_temp"),
+            updated_at,
+            attributes,
+        })
+    }
+
+    /// Get the ambient readings
+    pub fn sensors(&self) -> Sensors {
+        Sensors {
+            temperature: self.temperature,
+            humidity: self.humidity,
+            sampled_at: self.updated_at,
+        }
+    }
+
+    /// Get the comfort and eco setpoints
+    pub fn setpoints(&self) -> Setpoints {
+        Setpoints {
+            comfort: self.comfort_setpoint,
+            eco: self.eco_setpoint,
+        }
+    }
+}
+
+/// Write a temperature attribute with the encoding used by the device
+pub(crate) fn encode_temperature(attributes: &mut ControlAttributes, name: &str, temperature: Temperature, encoding: SetpointEncoding) {
+    let tenths = temperature.tenths();
+    match encoding {
+        SetpointEncoding::SplitBytes => {
+            attributes.insert(format!("{}H", name), (tenths >> 8) & 0xFF);
+            attributes.insert(format!("{}L", name), tenths & 0xFF);
+        }
+        SetpointEncoding::Tenths => {
+            attributes.insert(name, tenths);
+        }
+    }
+}
+
+/// Read an integer attribute
+fn int_attribute(attributes: &Map<String, Value>, name: &str) -> Option<i64> {
+    attributes.get(name).and_then(Value::as_i64)
+}
+
+/// Read a switch attribute, reported either as a boolean or as 0/1
+fn bool_attribute(attributes: &Map<String, Value>, name: &str) -> Option<bool> {
+    let value = attributes.get(name)?;
+    value.as_bool().or_else(|| value.as_i64().map(|v| v != 0))
+}
+
+/// Read a temperature in tenths of a degree, either as `name` or split over `nameH`/`nameL`
+fn temperature_attribute(attributes: &Map<String, Value>, name: &str) -> Option<Temperature> {
+    if let Some(tenths) = int_attribute(attributes, name) {
+        return Some(Temperature::from_tenths(tenths as i32));
+    }
+
+    let high = int_attribute(attributes, &format!("{}H", name))?;
+    let low = int_attribute(attributes, &format!("{}L", name))?;
+    Some(Temperature::from_tenths(((high << 8) | low) as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn decode(attributes: Value) -> Result<DeviceState, HeatzyError> {
+        let Value::Object(attributes) = attributes else {
+            panic!("attributes must be an object");
+        };
+        DeviceState::from_attributes("dev1", attributes, Some(1_700_000_000))
+    }
+
+    #[test]
+    fn decodes_pilote_v1_chinese_mode() {
+        let state = decode(json!({"mode": "经济"})).unwrap();
+
+        assert_eq!(state.mode, Some(DeviceMode::Eco));
+        assert_eq!(state.derogation_mode, None);
+        assert_eq!(state.lock_enabled, None);
+        assert_eq!(state.updated_at, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn decodes_pilote_v2_switches() {
+        let state = decode(json!({"mode": "cft", "timer_switch": 1, "lock_switch": 0})).unwrap();
+
+        assert_eq!(state.mode, Some(DeviceMode::Comfort));
+        assert_eq!(state.program_enabled, Some(true));
+        assert_eq!(state.lock_enabled, Some(false));
+        assert_eq!(state.temperature, None);
+    }
+
+    #[test]
+    fn decodes_pilote_v4_derogation() {
+        let state = decode(json!({
+            "mode": 4,
+            "derog_mode": 2,
+            "derog_time": 90,
+            "window_switch": true,
+        })).unwrap();
+
+        assert_eq!(state.mode, Some(DeviceMode::ComfortMinus1));
+        assert_eq!(state.derogation_mode, Some(DerogationMode::Boost));
+        assert_eq!(state.derogation_time, Some(90));
+        assert_eq!(state.window_detection_enabled, Some(true));
+    }
+
+    #[test]
+    fn decodes_pilote_pro_sensors_and_setpoints() {
+        let state = decode(json!({
+            "mode": 1,
+            "cur_temp": 195,
+            "cur_humi": 45,
+            "cft_temp": 200,
+            "eco_temp": 170,
+        })).unwrap();
+
+        assert_eq!(state.temperature, Some(Temperature::from_tenths(195)));
+        assert_eq!(state.humidity, Some(Humidity::from_percent(45.0)));
+        assert_eq!(state.setpoints(), Setpoints {
+            comfort: Some(Temperature::from_tenths(200)),
+            eco: Some(Temperature::from_tenths(170)),
+        });
+    }
+
+    #[test]
+    fn decodes_glow_split_bytes() {
+        let state = decode(json!({
+            "mode": "cft",
+            "cur_tempH": 0,
+            "cur_tempL": 215,
+            "cft_tempH": 1,
+            "cft_tempL": 0,
+            "eco_tempH": 0,
+            "eco_tempL": 180,
+            "LOCK_C": 1,
+        })).unwrap();
+
+        assert_eq!(state.temperature, Some(Temperature::from_tenths(215)));
+        assert_eq!(state.comfort_setpoint, Some(Temperature::from_tenths(256)));
+        assert_eq!(state.eco_setpoint, Some(Temperature::from_tenths(180)));
+        assert_eq!(state.lock_enabled, Some(true));
+    }
+
+    #[test]
+    fn unknown_modes_are_left_out() {
+        let state = decode(json!({"mode": "turbo", "derog_mode": 9, "timer_switch": 0})).unwrap();
+
+        assert_eq!(state.mode, None);
+        assert_eq!(state.derogation_mode, None);
+        assert_eq!(state.program_enabled, Some(false));
+        assert_eq!(state.attributes["mode"], json!("turbo"));
+    }
+
+    fn encode(tenths: i32, encoding: SetpointEncoding) -> ControlAttributes {
+        let mut attributes = ControlAttributes::new();
+        encode_temperature(&mut attributes, "cft_temp", Temperature::from_tenths(tenths), encoding);
+        attributes
+    }
+
+    #[test]
+    fn splits_temperatures_over_two_bytes() {
+        assert_eq!(
+            encode(Temperature::from_celsius(20.5).tenths(), SetpointEncoding::SplitBytes),
+            ControlAttributes::new().with("cft_tempH", 0).with("cft_tempL", 205)
+        );
+        assert_eq!(
+            encode(Temperature::from_celsius(25.6).tenths(), SetpointEncoding::SplitBytes),
+            ControlAttributes::new().with("cft_tempH", 1).with("cft_tempL", 0)
+        );
+        assert_eq!(
+            encode(300, SetpointEncoding::SplitBytes),
+            ControlAttributes::new().with("cft_tempH", 1).with("cft_tempL", 44)
+        );
+    }
+
+    #[test]
+    fn writes_tenths_in_a_single_attribute() {
+        assert_eq!(encode(205, SetpointEncoding::Tenths), ControlAttributes::new().with("cft_temp", 205));
+    }
+
+    #[test]
+    fn split_bytes_round_trip() {
+        for tenths in [50, 205, 255, 256, 300] {
+            let Value::Object(attributes) = serde_json::to_value(encode(tenths, SetpointEncoding::SplitBytes)).unwrap() else {
+                unreachable!();
+            };
+            assert_eq!(temperature_attribute(&attributes, "cft_temp"), Some(Temperature::from_tenths(tenths)));
+        }
+    }
+
+    #[test]
+    fn out_of_range_derogation_time_is_left_out() {
+        for time in [-1, i64::from(u32::MAX) + 1] {
+            let state = decode(json!({"mode": "cft", "derog_time": time})).unwrap();
+            assert_eq!(state.derogation_time, None);
+            assert_eq!(state.mode, Some(DeviceMode::Comfort));
+            assert_eq!(state.attributes["derog_time"], json!(time));
+        }
+    }
+}
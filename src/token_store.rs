@@ -0,0 +1,274 @@
+use crate::error::HeatzyError;
+use crate::models::AuthResponse;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Key identifying a cached token
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TokenKey {
+    /// Account name (username or email)
+    pub account: String,
+    /// Region name, or the base URL when it was overridden
+    pub region: String,
+}
+
+/// Storage for authentication tokens
+///
+/// Implementations must be safe to share between tasks; the client only calls
+/// them around logins so they are allowed to block.
+pub trait TokenStore: Send + Sync {
+    /// Load the token saved for this key
+    fn load(&self, key: &TokenKey) -> Result<Option<AuthResponse>, HeatzyError>;
+
+    /// Save the token for this key, replacing any previous one
+    fn save(&self, key: &TokenKey, auth_response: &AuthResponse) -> Result<(), HeatzyError>;
+
+    /// Remove the token saved for this key
+    fn clear(&self, key: &TokenKey) -> Result<(), HeatzyError>;
+
+    /// List the keys having a saved token
+    fn keys(&self) -> Result<Vec<TokenKey>, HeatzyError>;
+}
+
+/// Token store keeping tokens in memory only
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<HashMap<TokenKey, AuthResponse>>,
+}
+
+impl MemoryTokenStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self, key: &TokenKey) -> Result<Option<AuthResponse>, HeatzyError> {
+        Ok(self.tokens.lock().unwrap().get(key).cloned())
+    }
+
+    fn save(&self, key: &TokenKey, auth_response: &AuthResponse) -> Result<(), HeatzyError> {
+        self.tokens.lock().unwrap().insert(key.clone(), auth_response.clone());
+        Ok(())
+    }
+
+    fn clear(&self, key: &TokenKey) -> Result<(), HeatzyError> {
+        self.tokens.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn keys(&self) -> Result<Vec<TokenKey>, HeatzyError> {
+        Ok(self.tokens.lock().unwrap().keys().cloned().collect())
+    }
+}
+
+/// Entry of the token file
+#[derive(Debug, Serialize, Deserialize)]
+struct FileEntry {
+    #[serde(flatten)]
+    key: TokenKey,
+    #[serde(flatten)]
+    auth_response: AuthResponse,
+}
+
+/// Token store persisting tokens in a JSON file
+///
+/// The file is only readable by its owner (mode 0600 on Unix).
+#[derive(Debug)]
+pub struct FileTokenStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileTokenStore {
+    /// Create a store backed by the given file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Create a store in the user cache directory (`$XDG_CACHE_HOME/heatzy/tokens.json` on Linux)
+    pub fn default_location() -> Result<Self, HeatzyError> {
+        let cache_dir = dirs::cache_dir()
+            .ok_or_else(|| HeatzyError::TokenStore("Cannot determine the cache directory".to_string()))?;
+        Ok(Self::new(cache_dir.join("heatzy").join("tokens.json")))
+    }
+
+    /// Path of the backing file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read the saved entries, treating an unreadable file as empty so it gets replaced
+    fn read_entries(&self) -> Result<Vec<FileEntry>, HeatzyError> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(HeatzyError::TokenStore(format!("Failed to read {}: {}", self.path.display(), e))),
+        };
+
+        match serde_json::from_str(&content) {
+            Ok(entries) => Ok(entries),
+            Err(e) => {
+                warn!("Ignoring corrupt token file {}: {}", self.path.display(), e);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Replace the file atomically, so an interrupted write never leaves it corrupt
+    fn write_entries(&self, entries: &[FileEntry]) -> Result<(), HeatzyError> {
+        let io_error = |e: std::io::Error| HeatzyError::TokenStore(format!("Failed to write {}: {}", self.path.display(), e));
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+
+        let content = serde_json::to_string_pretty(entries)
+            .map_err(|e| HeatzyError::TokenStore(format!("Failed to serialize tokens: {}", e)))?;
+
+        // Written next to the target so the rename stays on the same file system
+        let mut temp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = self.path.with_file_name(temp_name);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let result = options.open(&temp_path)
+            .and_then(|mut file| {
+                // The mode only applies on creation, fix up a leftover file
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+                }
+                file.write_all(content.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&temp_path, &self.path));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temp_path);
+            return Err(io_error(e));
+        }
+
+        debug!("Saved {} token(s) to {}", entries.len(), self.path.display());
+        Ok(())
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self, key: &TokenKey) -> Result<Option<AuthResponse>, HeatzyError> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.read_entries()?
+            .into_iter()
+            .find(|entry| &entry.key == key)
+            .map(|entry| entry.auth_response))
+    }
+
+    fn save(&self, key: &TokenKey, auth_response: &AuthResponse) -> Result<(), HeatzyError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entries = self.read_entries()?;
+        entries.retain(|entry| &entry.key != key);
+        entries.push(FileEntry {
+            key: key.clone(),
+            auth_response: auth_response.clone(),
+        });
+        self.write_entries(&entries)
+    }
+
+    fn clear(&self, key: &TokenKey) -> Result<(), HeatzyError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entries = self.read_entries()?;
+        let count = entries.len();
+        entries.retain(|entry| &entry.key != key);
+        if entries.len() == count {
+            return Ok(());
+        }
+        self.write_entries(&entries)
+    }
+
+    fn keys(&self) -> Result<Vec<TokenKey>, HeatzyError> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.read_entries()?.into_iter().map(|entry| entry.key).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> FileTokenStore {
+        let dir = std::env::temp_dir().join(format!("heatzy-token-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        FileTokenStore::new(dir.join("tokens.json"))
+    }
+
+    fn key(account: &str) -> TokenKey {
+        TokenKey {
+            account: account.to_string(),
+            region: "eu".to_string(),
+        }
+    }
+
+    fn auth_response(token: &str) -> AuthResponse {
+        AuthResponse {
+            token: token.to_string(),
+            uid: "uid".to_string(),
+            expire_at: 1_900_000_000,
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_tokens() {
+        let store = store("roundtrip");
+        store.save(&key("a"), &auth_response("first")).unwrap();
+        store.save(&key("b"), &auth_response("second")).unwrap();
+        store.save(&key("a"), &auth_response("third")).unwrap();
+
+        assert_eq!(store.load(&key("a")).unwrap().unwrap().token, "third");
+        assert_eq!(store.load(&key("b")).unwrap().unwrap().token, "second");
+        assert_eq!(store.keys().unwrap().len(), 2);
+
+        store.clear(&key("a")).unwrap();
+        assert!(store.load(&key("a")).unwrap().is_none());
+    }
+
+    #[test]
+    fn corrupt_file_is_replaced_on_save() {
+        let store = store("corrupt");
+        fs::create_dir_all(store.path().parent().unwrap()).unwrap();
+        fs::write(store.path(), "[{\"account\": \"a\", \"tok").unwrap();
+
+        assert!(store.load(&key("a")).unwrap().is_none());
+        store.save(&key("a"), &auth_response("token")).unwrap();
+        assert_eq!(store.load(&key("a")).unwrap().unwrap().token, "token");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_is_private_and_no_temporary_file_is_left() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let store = store("private");
+        store.save(&key("a"), &auth_response("token")).unwrap();
+
+        let mode = fs::metadata(store.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let files = fs::read_dir(store.path().parent().unwrap()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+}
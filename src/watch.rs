@@ -0,0 +1,397 @@
+use crate::error::HeatzyError;
+use crate::websocket::DeviceEvent;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How much slower than the requested interval a quiet or offline device is polled, at most
+const MAX_SLOWDOWN: u32 = 8;
+
+/// How much slower than the requested interval the device listing, giving
+/// connectivity, is refreshed
+const LISTING_SLOWDOWN: u32 = 4;
+
+/// Polling state of a watched device
+#[derive(Debug)]
+struct WatchedDevice {
+    did: String,
+    /// Attributes of the last report, `None` until the first one
+    attributes: Option<Map<String, Value>>,
+    /// Timestamp of the last report
+    updated_at: Option<i64>,
+    /// Last known connectivity, `None` until the first device listing
+    online: Option<bool>,
+    /// Delay before the next poll of this device
+    delay: Duration,
+    next_poll: Instant,
+}
+
+/// Snapshots of the watched devices, turned into change events by diffing
+///
+/// Devices that keep reporting the same attributes, are offline or fail are
+/// polled less and less often, up to [`MAX_SLOWDOWN`] times the interval; a
+/// change or a device coming back online restores the interval. Connectivity
+/// comes from the device listing, refreshed on its own slower schedule.
+#[derive(Debug)]
+pub(crate) struct Watcher {
+    devices: Vec<WatchedDevice>,
+    interval: Duration,
+    /// Delay before the next device listing
+    listing_delay: Duration,
+    next_listing: Instant,
+    /// Events and errors not yielded yet
+    pub pending: VecDeque<Result<DeviceEvent, HeatzyError>>,
+}
+
+impl Watcher {
+    /// Watch the given devices, polling them first right away
+    pub fn new(device_ids: &[&str], interval: Duration) -> Self {
+        let interval = interval.max(Duration::from_secs(1));
+        let now = Instant::now();
+        Self {
+            devices: device_ids.iter()
+                .map(|did| WatchedDevice {
+                    did: did.to_string(),
+                    attributes: None,
+                    updated_at: None,
+                    online: None,
+                    delay: interval,
+                    next_poll: now,
+                })
+                .collect(),
+            interval,
+            listing_delay: interval * LISTING_SLOWDOWN,
+            next_listing: now,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Time at which the next device is due
+    pub fn next_poll(&self) -> Instant {
+        self.devices.iter()
+            .map(|device| device.next_poll)
+            .min()
+            .unwrap_or_else(|| Instant::now() + self.interval)
+    }
+
+    /// Time at which the next device poll or listing is due
+    pub fn next_wake_up(&self) -> Instant {
+        self.next_poll().min(self.next_listing)
+    }
+
+    /// Check whether the device listing should be refreshed
+    pub fn listing_due(&self, now: Instant) -> bool {
+        self.next_listing <= now
+    }
+
+    /// Record the connectivity of every listed device
+    pub fn record_listing<'a>(&mut self, devices: impl IntoIterator<Item = (&'a str, bool)>, now: Instant) {
+        for (did, online) in devices {
+            self.record_online(did, online, now);
+        }
+        self.listing_delay = self.interval * LISTING_SLOWDOWN;
+        self.next_listing = now + self.listing_delay;
+    }
+
+    /// Record a failed device listing, queuing the error
+    ///
+    /// Devices keep being polled with the connectivity known so far.
+    pub fn record_listing_error(&mut self, error: HeatzyError, now: Instant) {
+        self.listing_delay = (self.listing_delay * 2).min(self.max_delay());
+        self.next_listing = now + self.listing_delay;
+        self.pending.push_back(Err(error));
+    }
+
+    /// Devices to fetch the attributes of, rescheduling offline due devices
+    pub fn due(&mut self, now: Instant) -> Vec<String> {
+        let max_delay = self.max_delay();
+        let mut due = Vec::new();
+        for device in self.devices.iter_mut().filter(|device| device.next_poll <= now) {
+            if device.online == Some(false) {
+                slow_down(device, now, 2.0, max_delay);
+            } else {
+                due.push(device.did.clone());
+            }
+        }
+        due
+    }
+
+    /// Record the connectivity of a device, queuing an event when it changed
+    pub fn record_online(&mut self, device_id: &str, online: bool, now: Instant) {
+        let interval = self.interval;
+        let Some(device) = self.device_mut(device_id) else {
+            return;
+        };
+        if device.online == Some(online) {
+            return;
+        }
+
+        if online && device.online.is_some() {
+            // Back online: catch up on what changed meanwhile
+            device.delay = interval;
+            device.next_poll = now;
+        }
+        device.online = Some(online);
+        self.pending.push_back(Ok(DeviceEvent::Connectivity {
+            did: device_id.to_string(),
+            online,
+        }));
+    }
+
+    /// Record a report of a device, queuing the attributes that changed since the previous one
+    pub fn record_attributes(&mut self, device_id: &str, attributes: Map<String, Value>, updated_at: Option<i64>, now: Instant) {
+        let interval = self.interval;
+        let max_delay = self.max_delay();
+        let Some(device) = self.device_mut(device_id) else {
+            return;
+        };
+
+        if updated_at.is_some() && updated_at == device.updated_at {
+            slow_down(device, now, 1.5, max_delay);
+            return;
+        }
+
+        let mut changed: Map<String, Value> = match &device.attributes {
+            Some(previous) => attributes.iter()
+                .filter(|(name, value)| previous.get(*name) != Some(*value))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            None => attributes.clone(),
+        };
+        // Keep both bytes of split values so the change can be decoded
+        let siblings: Vec<String> = changed.keys().filter_map(|name| split_sibling(name)).collect();
+        for sibling in siblings {
+            if let Some(value) = attributes.get(&sibling) {
+                changed.entry(sibling).or_insert_with(|| value.clone());
+            }
+        }
+        device.attributes = Some(attributes);
+        device.updated_at = updated_at;
+
+        if changed.is_empty() {
+            slow_down(device, now, 1.5, max_delay);
+            return;
+        }
+
+        device.delay = interval;
+        device.next_poll = now + interval;
+        self.pending.push_back(Ok(DeviceEvent::Attributes {
+            did: device_id.to_string(),
+            attrs: changed,
+        }));
+    }
+
+    /// Record a failed poll of a device, queuing the error
+    pub fn record_error(&mut self, device_id: &str, error: HeatzyError, now: Instant) {
+        if error.is_device_offline() {
+            self.record_online(device_id, false, now);
+            let max_delay = self.max_delay();
+            if let Some(device) = self.device_mut(device_id) {
+                slow_down(device, now, 2.0, max_delay);
+            }
+            return;
+        }
+
+        let max_delay = self.max_delay();
+        if let Some(device) = self.device_mut(device_id) {
+            slow_down(device, now, 2.0, max_delay);
+        }
+        self.pending.push_back(Err(error));
+    }
+
+    fn max_delay(&self) -> Duration {
+        self.interval * MAX_SLOWDOWN
+    }
+
+    fn device_mut(&mut self, device_id: &str) -> Option<&mut WatchedDevice> {
+        self.devices.iter_mut().find(|device| device.did == device_id)
+    }
+}
+
+/// Name of the other byte of a value split over `<name>H` and `<name>L` attributes
+fn split_sibling(name: &str) -> Option<String> {
+    if let Some(base) = name.strip_suffix('H') {
+        Some(format!("{}L", base))
+    } else {
+        name.strip_suffix('L').map(|base| format!("{}H", base))
+    }
+}
+
+/// Grow the delay of a device and schedule its next poll
+fn slow_down(device: &mut WatchedDevice, now: Instant, factor: f64, max_delay: Duration) {
+    device.delay = device.delay.mul_f64(factor).min(max_delay);
+    device.next_poll = now + device.delay;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::GizwitsErrorCode;
+    use crate::models::DeviceMode;
+    use crate::state::Temperature;
+    use serde_json::json;
+
+    const INTERVAL: Duration = Duration::from_secs(10);
+
+    fn attributes(mode: &str, temperature: i64) -> Map<String, Value> {
+        let mut attributes = Map::new();
+        attributes.insert("mode".to_string(), json!(mode));
+        attributes.insert("cur_temp".to_string(), json!(temperature));
+        attributes
+    }
+
+    fn events(watcher: &mut Watcher) -> Vec<DeviceEvent> {
+        watcher.pending.drain(..).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn first_report_is_yielded_whole() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+
+        watcher.record_attributes("dev1", attributes("cft", 200), Some(1), now);
+
+        assert_eq!(events(&mut watcher), vec![DeviceEvent::Attributes {
+            did: "dev1".to_string(),
+            attrs: attributes("cft", 200),
+        }]);
+    }
+
+    #[test]
+    fn later_reports_yield_only_changes() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+        watcher.record_attributes("dev1", attributes("cft", 200), Some(1), now);
+        watcher.pending.clear();
+
+        watcher.record_attributes("dev1", attributes("eco", 200), Some(2), now);
+
+        let mut changed = Map::new();
+        changed.insert("mode".to_string(), json!("eco"));
+        assert_eq!(events(&mut watcher), vec![DeviceEvent::Attributes { did: "dev1".to_string(), attrs: changed }]);
+    }
+
+    #[test]
+    fn unchanged_reports_are_dropped_and_slow_polling_down() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+        watcher.record_attributes("dev1", attributes("cft", 200), Some(1), now);
+        watcher.pending.clear();
+
+        watcher.record_attributes("dev1", attributes("cft", 200), Some(2), now);
+        assert!(watcher.pending.is_empty());
+        assert_eq!(watcher.next_poll(), now + INTERVAL.mul_f64(1.5));
+
+        for _ in 0..20 {
+            watcher.record_attributes("dev1", attributes("cft", 200), Some(2), now);
+        }
+        assert_eq!(watcher.next_poll(), now + INTERVAL * MAX_SLOWDOWN);
+
+        watcher.record_attributes("dev1", attributes("eco", 200), Some(3), now);
+        assert_eq!(watcher.next_poll(), now + INTERVAL);
+    }
+
+    #[test]
+    fn changes_decode_into_a_partial_state() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+        let mut report = attributes("cft", 200);
+        report.insert("cft_tempH".to_string(), json!(0));
+        report.insert("cft_tempL".to_string(), json!(200));
+        watcher.record_attributes("dev1", report.clone(), Some(1), now);
+        watcher.pending.clear();
+
+        report.insert("mode".to_string(), json!("eco"));
+        report.insert("cft_tempL".to_string(), json!(210));
+        watcher.record_attributes("dev1", report, Some(2), now);
+
+        let event = events(&mut watcher).remove(0);
+        let state = event.state().unwrap().unwrap();
+        assert_eq!(state.mode, Some(DeviceMode::Eco));
+        assert_eq!(state.comfort_setpoint, Some(Temperature::from_tenths(210)));
+        assert_eq!(state.temperature, None);
+        assert_eq!(DeviceEvent::Reconnected.state().unwrap().map(|state| state.did), None);
+    }
+
+    #[test]
+    fn devices_are_listed_less_often_than_polled() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+        assert!(watcher.listing_due(now));
+
+        watcher.record_listing([("dev1", true)], now);
+        watcher.record_attributes("dev1", attributes("cft", 200), Some(1), now);
+
+        assert_eq!(watcher.next_wake_up(), now + INTERVAL);
+        assert!(!watcher.listing_due(now + INTERVAL));
+        assert!(watcher.listing_due(now + INTERVAL * LISTING_SLOWDOWN));
+    }
+
+    #[test]
+    fn failed_listings_back_off_without_stopping_polls() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+
+        watcher.record_listing_error(HeatzyError::Api("boom".to_string()), now);
+
+        assert!(matches!(watcher.pending.pop_front(), Some(Err(HeatzyError::Api(_)))));
+        assert_eq!(watcher.due(now), vec!["dev1".to_string()]);
+        assert_eq!(watcher.next_listing, now + INTERVAL * MAX_SLOWDOWN);
+
+        watcher.record_listing([("dev1", true)], now);
+        assert_eq!(watcher.next_listing, now + INTERVAL * LISTING_SLOWDOWN);
+    }
+
+    #[test]
+    fn connectivity_changes_are_yielded_once() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+
+        watcher.record_online("dev1", true, now);
+        watcher.record_online("dev1", true, now);
+        watcher.record_online("dev1", false, now);
+        watcher.record_online("other", false, now);
+
+        assert_eq!(events(&mut watcher), vec![
+            DeviceEvent::Connectivity { did: "dev1".to_string(), online: true },
+            DeviceEvent::Connectivity { did: "dev1".to_string(), online: false },
+        ]);
+    }
+
+    #[test]
+    fn offline_devices_back_off_until_back_online() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+        watcher.record_online("dev1", false, now);
+
+        assert!(watcher.due(now).is_empty());
+        assert_eq!(watcher.next_poll(), now + INTERVAL * 2);
+
+        let later = now + INTERVAL * 2;
+        assert!(watcher.due(later).is_empty());
+        assert_eq!(watcher.next_poll(), later + INTERVAL * 4);
+
+        watcher.record_online("dev1", true, later);
+        assert_eq!(watcher.due(later), vec!["dev1".to_string()]);
+    }
+
+    #[test]
+    fn errors_are_yielded_and_offline_errors_become_events() {
+        let mut watcher = Watcher::new(&["dev1"], INTERVAL);
+        let now = Instant::now();
+
+        watcher.record_error("dev1", HeatzyError::Api("boom".to_string()), now);
+        assert!(matches!(watcher.pending.pop_front(), Some(Err(HeatzyError::Api(_)))));
+        assert_eq!(watcher.next_poll(), now + INTERVAL * 2);
+
+        let offline = HeatzyError::Gizwits {
+            status: reqwest::StatusCode::BAD_REQUEST,
+            code: GizwitsErrorCode::DeviceOffline,
+            message: "device offline".to_string(),
+            detail: None,
+        };
+        watcher.record_error("dev1", offline, now);
+        assert_eq!(events(&mut watcher), vec![DeviceEvent::Connectivity { did: "dev1".to_string(), online: false }]);
+    }
+}
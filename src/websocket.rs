@@ -0,0 +1,565 @@
+use crate::client::Region;
+use crate::error::{GizwitsErrorCode, HeatzyError};
+use crate::models::ControlAttributes;
+use crate::retry::RetryPolicy;
+use crate::state::DeviceState;
+use futures::future::BoxFuture;
+use futures::{Future, FutureExt, SinkExt, Stream, StreamExt};
+use log::{debug, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+const EVENT_BUFFER: usize = 256;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Function returning a valid token, called before each reconnection
+type TokenProvider = Arc<dyn Fn() -> BoxFuture<'static, Result<String, HeatzyError>> + Send + Sync>;
+
+/// Change reported by the Gizwits websocket
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceEvent {
+    /// Attributes reported by a device, usually only the changed ones
+    Attributes {
+        did: String,
+        attrs: Map<String, Value>,
+    },
+    /// Device came online or went offline
+    Connectivity {
+        did: String,
+        online: bool,
+    },
+    /// Connection to the server was lost, events may be missed until [`DeviceEvent::Reconnected`]
+    Disconnected,
+    /// Connection to the server was restored and the devices subscribed again
+    Reconnected,
+}
+
+impl DeviceEvent {
+    /// Device the event is about, if any
+    pub fn device_id(&self) -> Option<&str> {
+        match self {
+            DeviceEvent::Attributes { did, .. } | DeviceEvent::Connectivity { did, .. } => Some(did),
+            DeviceEvent::Disconnected | DeviceEvent::Reconnected => None,
+        }
+    }
+
+    /// Decode the attributes of a [`DeviceEvent::Attributes`] event
+    ///
+    /// Only the reported attributes are set in the state, so fields of a
+    /// partial report are `None` when unchanged.
+    pub fn state(&self) -> Result<Option<DeviceState>, HeatzyError> {
+        match self {
+            DeviceEvent::Attributes { did, attrs } => DeviceState::from_attributes(did, attrs.clone(), None).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Builder for [`WebSocketSession`]
+pub struct WebSocketSessionBuilder {
+    url: String,
+    app_id: String,
+    uid: String,
+    token: String,
+    token_provider: Option<TokenProvider>,
+    devices: Vec<String>,
+    heartbeat_interval: Duration,
+    reconnect_policy: RetryPolicy,
+}
+
+impl WebSocketSessionBuilder {
+    /// Override the websocket URL, e.g. for a local test server
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Use the websocket server of a region (defaults to Europe)
+    pub fn region(mut self, region: Region) -> Self {
+        self.url = region.websocket_url().to_string();
+        self
+    }
+
+    /// Subscribe to these devices only instead of every bound device
+    pub fn devices<I, D>(mut self, devices: I) -> Self
+    where
+        I: IntoIterator<Item = D>,
+        D: Into<String>,
+    {
+        self.devices = devices.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Interval between heartbeats (defaults to 60 seconds, the server allows up to 180)
+    ///
+    /// The connection is considered lost when a heartbeat is not answered
+    /// before the next one is due.
+    pub fn heartbeat_interval(mut self, heartbeat_interval: Duration) -> Self {
+        self.heartbeat_interval = heartbeat_interval.max(Duration::from_secs(1));
+        self
+    }
+
+    /// Set the delays between reconnection attempts
+    ///
+    /// The session gives up after `max_attempts` consecutive failed attempts.
+    pub fn reconnect_policy(mut self, reconnect_policy: RetryPolicy) -> Self {
+        self.reconnect_policy = reconnect_policy;
+        self
+    }
+
+    /// Fetch the token with this function before each reconnection
+    ///
+    /// Without it the initial token is reused, so reconnecting fails once it
+    /// has expired.
+    pub fn token_provider<F, Fut>(mut self, token_provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, HeatzyError>> + Send + 'static,
+    {
+        self.token_provider = Some(Arc::new(move || token_provider().boxed()));
+        self
+    }
+
+    /// Connect, log in and subscribe to the devices
+    pub async fn connect(self) -> Result<WebSocketSession, HeatzyError> {
+        let config = SessionConfig {
+            url: self.url,
+            app_id: self.app_id,
+            uid: self.uid,
+            token: self.token,
+            token_provider: self.token_provider,
+            devices: self.devices,
+            heartbeat_interval: self.heartbeat_interval,
+            reconnect_policy: self.reconnect_policy,
+        };
+
+        info!("Connecting to websocket {}", config.url);
+        let socket = open(&config, &config.token).await?;
+        info!("Websocket session established");
+
+        let (command_sender, command_receiver) = mpsc::unbounded_channel();
+        let (event_sender, event_receiver) = mpsc::channel(EVENT_BUFFER);
+        let task = tokio::spawn(run(config, socket, command_receiver, event_sender));
+
+        Ok(WebSocketSession {
+            commands: command_sender,
+            events: event_receiver,
+            task,
+        })
+    }
+}
+
+/// Real-time session on the Gizwits websocket
+///
+/// The session is a [`Stream`] of [`DeviceEvent`]. Heartbeats and reconnections
+/// are handled in the background; the stream ends when the server rejects the
+/// login after a reconnection (e.g. the token expired and no
+/// [token provider](WebSocketSessionBuilder::token_provider) was set) or when
+/// reconnecting keeps failing.
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use heatzy::Client;
+/// use std::sync::Arc;
+///
+/// # async fn example(client: Arc<Client>) -> Result<(), heatzy::HeatzyError> {
+/// let mut session = client.websocket_session(&[]).await?;
+/// while let Some(event) = session.next().await {
+///     println!("{:?}", event);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct WebSocketSession {
+    commands: mpsc::UnboundedSender<Command>,
+    events: mpsc::Receiver<DeviceEvent>,
+    task: JoinHandle<()>,
+}
+
+impl WebSocketSession {
+    /// Create a builder for a session authenticated with the given user token
+    pub fn builder(app_id: impl Into<String>, uid: impl Into<String>, token: impl Into<String>) -> WebSocketSessionBuilder {
+        WebSocketSessionBuilder {
+            url: Region::default().websocket_url().to_string(),
+            app_id: app_id.into(),
+            uid: uid.into(),
+            token: token.into(),
+            token_provider: None,
+            devices: Vec::new(),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            reconnect_policy: RetryPolicy {
+                max_attempts: 10,
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(60),
+                ..RetryPolicy::default()
+            },
+        }
+    }
+
+    /// Write attributes to a device over the socket
+    ///
+    /// Fails if the connection is currently lost.
+    pub async fn write(&self, device_id: &str, attrs: &ControlAttributes) -> Result<(), HeatzyError> {
+        debug!("Writing attributes to {} over websocket", device_id);
+        let attrs = serde_json::to_value(attrs)
+            .map_err(|e| HeatzyError::InvalidValue(format!("Cannot encode attributes: {}", e)))?;
+        self.send(Outgoing::C2sWrite {
+            did: device_id.to_string(),
+            attrs,
+        }).await
+    }
+
+    /// Ask a device to report all its attributes, received as a [`DeviceEvent::Attributes`]
+    pub async fn read(&self, device_id: &str) -> Result<(), HeatzyError> {
+        debug!("Reading attributes of {} over websocket", device_id);
+        self.send(Outgoing::C2sRead {
+            did: device_id.to_string(),
+        }).await
+    }
+
+    /// Close the connection
+    pub async fn close(self) {
+        let WebSocketSession { commands, events, task } = self;
+        drop(events);
+        drop(commands);
+        let _ = task.await;
+    }
+
+    async fn send(&self, message: Outgoing) -> Result<(), HeatzyError> {
+        let (reply, outcome) = oneshot::channel();
+        self.commands
+            .send(Command { message, reply })
+            .map_err(|_| HeatzyError::from(tungstenite::Error::AlreadyClosed))?;
+        outcome.await.map_err(|_| HeatzyError::from(tungstenite::Error::AlreadyClosed))?
+    }
+}
+
+impl Stream for WebSocketSession {
+    type Item = DeviceEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DeviceEvent>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// Settings shared by the initial connection and the reconnections
+struct SessionConfig {
+    url: String,
+    app_id: String,
+    uid: String,
+    /// Token of the initial connection, reused when there is no provider
+    token: String,
+    token_provider: Option<TokenProvider>,
+    devices: Vec<String>,
+    heartbeat_interval: Duration,
+    reconnect_policy: RetryPolicy,
+}
+
+impl SessionConfig {
+    /// Get the token to log in with on a reconnection
+    async fn fresh_token(&self) -> Result<String, HeatzyError> {
+        match &self.token_provider {
+            Some(token_provider) => token_provider().await,
+            None => Ok(self.token.clone()),
+        }
+    }
+}
+
+/// Message to send on behalf of the session owner
+struct Command {
+    message: Outgoing,
+    reply: oneshot::Sender<Result<(), HeatzyError>>,
+}
+
+/// Why the connection loop stopped
+enum Outcome {
+    /// The session or its event stream was dropped
+    Closed,
+    /// The connection was lost and should be opened again
+    Lost(String),
+}
+
+/// Messages sent to the server
+#[derive(Debug, Serialize)]
+#[serde(tag = "cmd", content = "data", rename_all = "snake_case")]
+enum Outgoing {
+    LoginReq {
+        appid: String,
+        uid: String,
+        token: String,
+        p0_type: &'static str,
+        heartbeat_interval: u64,
+        auto_subscribe: bool,
+    },
+    SubscribeReq(Vec<DeviceRef>),
+    C2sWrite {
+        did: String,
+        attrs: Value,
+    },
+    C2sRead {
+        did: String,
+    },
+    Ping,
+}
+
+/// Messages received from the server
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", content = "data", rename_all = "snake_case")]
+enum Incoming {
+    LoginRes {
+        success: bool,
+    },
+    SubscribeRes {
+        #[serde(default)]
+        failed: Vec<DeviceRef>,
+    },
+    S2cNoti {
+        did: String,
+        #[serde(default)]
+        attrs: Map<String, Value>,
+    },
+    S2cOnlineStatus {
+        did: String,
+        online: bool,
+    },
+    S2cInvalidMsg {
+        error_code: Option<u32>,
+        #[serde(default)]
+        msg: String,
+    },
+    Pong,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DeviceRef {
+    did: String,
+}
+
+/// Open a connection, log in and subscribe to the devices
+async fn open(config: &SessionConfig, token: &str) -> Result<Socket, HeatzyError> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(config.url.as_str()).await?;
+
+    send_message(&mut socket, &Outgoing::LoginReq {
+        appid: config.app_id.clone(),
+        uid: config.uid.clone(),
+        token: token.to_string(),
+        p0_type: "attrs_v4",
+        heartbeat_interval: config.heartbeat_interval.as_secs().max(1),
+        auto_subscribe: config.devices.is_empty(),
+    }).await?;
+
+    let success = tokio::time::timeout(LOGIN_TIMEOUT, wait_login(&mut socket))
+        .await
+        .map_err(|_| HeatzyError::Api("Timed out waiting for the websocket login response".to_string()))??;
+    if !success {
+        return Err(HeatzyError::Auth("Websocket login rejected".to_string()));
+    }
+    debug!("Websocket login succeeded");
+
+    if !config.devices.is_empty() {
+        let devices = config.devices.iter().map(|did| DeviceRef { did: did.clone() }).collect();
+        send_message(&mut socket, &Outgoing::SubscribeReq(devices)).await?;
+    }
+
+    Ok(socket)
+}
+
+/// Wait for the login response, ignoring anything received before it
+async fn wait_login(socket: &mut Socket) -> Result<bool, HeatzyError> {
+    while let Some(message) = socket.next().await {
+        if let Message::Text(text) = message? {
+            if let Ok(Incoming::LoginRes { success }) = serde_json::from_str(&text) {
+                return Ok(success);
+            }
+        }
+    }
+    Err(HeatzyError::from(tungstenite::Error::ConnectionClosed))
+}
+
+async fn send_message(socket: &mut Socket, message: &Outgoing) -> Result<(), HeatzyError> {
+    let text = serde_json::to_string(message)
+        .map_err(|e| HeatzyError::InvalidValue(format!("Cannot encode websocket message: {}", e)))?;
+    trace!("Websocket send: {}", text);
+    socket.send(Message::Text(text)).await?;
+    Ok(())
+}
+
+/// Background task running the connection until the session is dropped
+async fn run(
+    config: SessionConfig,
+    mut socket: Socket,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    events: mpsc::Sender<DeviceEvent>,
+) {
+    loop {
+        let reason = match drive(&config, &mut socket, &mut commands, &events).await {
+            Outcome::Closed => {
+                debug!("Websocket session closed");
+                let _ = socket.close(None).await;
+                return;
+            }
+            Outcome::Lost(reason) => reason,
+        };
+
+        warn!("Websocket connection lost: {}", reason);
+        if events.send(DeviceEvent::Disconnected).await.is_err() {
+            return;
+        }
+
+        socket = match reconnect(&config, &mut commands).await {
+            Some(socket) => socket,
+            None => return,
+        };
+
+        info!("Websocket session re-established");
+        if events.send(DeviceEvent::Reconnected).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Forward events and commands until the connection is lost or the session dropped
+async fn drive(
+    config: &SessionConfig,
+    socket: &mut Socket,
+    commands: &mut mpsc::UnboundedReceiver<Command>,
+    events: &mpsc::Sender<DeviceEvent>,
+) -> Outcome {
+    let mut heartbeat = tokio::time::interval_at(
+        tokio::time::Instant::now() + config.heartbeat_interval,
+        config.heartbeat_interval,
+    );
+    let mut awaiting_pong = false;
+
+    loop {
+        tokio::select! {
+            message = socket.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(frame))) => {
+                        return Outcome::Lost(format!("closed by server ({:?})", frame));
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Outcome::Lost(e.to_string()),
+                    None => return Outcome::Lost("connection closed".to_string()),
+                };
+                trace!("Websocket receive: {}", text);
+
+                let event = match serde_json::from_str::<Incoming>(&text) {
+                    Ok(Incoming::S2cNoti { did, attrs }) => DeviceEvent::Attributes { did, attrs },
+                    Ok(Incoming::S2cOnlineStatus { did, online }) => DeviceEvent::Connectivity { did, online },
+                    Ok(Incoming::Pong) => {
+                        awaiting_pong = false;
+                        continue;
+                    }
+                    Ok(Incoming::SubscribeRes { failed }) => {
+                        for device in failed {
+                            warn!("Websocket subscription failed for {}", device.did);
+                        }
+                        continue;
+                    }
+                    Ok(Incoming::S2cInvalidMsg { error_code, msg }) => {
+                        warn!("Websocket server rejected a message (code {:?}): {}", error_code, msg);
+                        continue;
+                    }
+                    Ok(Incoming::LoginRes { .. } | Incoming::Other) => continue,
+                    Err(e) => {
+                        debug!("Ignoring unexpected websocket message: {}", e);
+                        continue;
+                    }
+                };
+                if events.send(event).await.is_err() {
+                    return Outcome::Closed;
+                }
+            }
+            command = commands.recv() => {
+                let Some(Command { message, reply }) = command else {
+                    return Outcome::Closed;
+                };
+                match send_message(socket, &message).await {
+                    Ok(()) => {
+                        let _ = reply.send(Ok(()));
+                    }
+                    Err(e) => {
+                        let reason = e.to_string();
+                        let _ = reply.send(Err(e));
+                        return Outcome::Lost(reason);
+                    }
+                }
+            }
+            _ = heartbeat.tick() => {
+                if awaiting_pong {
+                    return Outcome::Lost("heartbeat not answered".to_string());
+                }
+                if let Err(e) = send_message(socket, &Outgoing::Ping).await {
+                    return Outcome::Lost(e.to_string());
+                }
+                awaiting_pong = true;
+            }
+        }
+    }
+}
+
+/// Check whether the token or credentials were refused, so trying again cannot help
+fn is_login_rejected(error: &HeatzyError) -> bool {
+    matches!(error, HeatzyError::Auth(_) | HeatzyError::NoToken)
+        || error.is_token_invalid()
+        || matches!(error.gizwits_code(), Some(GizwitsErrorCode::InvalidCredentials | GizwitsErrorCode::UserNotExist))
+}
+
+/// Open the connection again, failing the commands received meanwhile
+///
+/// Returns `None` when the session was dropped, the login was rejected or the
+/// attempts are exhausted.
+async fn reconnect(config: &SessionConfig, commands: &mut mpsc::UnboundedReceiver<Command>) -> Option<Socket> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let delay = tokio::time::sleep(config.reconnect_policy.backoff(attempt));
+        tokio::pin!(delay);
+
+        loop {
+            tokio::select! {
+                _ = &mut delay => break,
+                command = commands.recv() => match command {
+                    Some(Command { reply, .. }) => {
+                        let _ = reply.send(Err(HeatzyError::from(tungstenite::Error::ConnectionClosed)));
+                    }
+                    None => return None,
+                },
+            }
+        }
+
+        debug!("Reconnecting to websocket (attempt {})", attempt);
+        let result = match config.fresh_token().await {
+            Ok(token) => open(config, &token).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(socket) => return Some(socket),
+            Err(e) if is_login_rejected(&e) => {
+                warn!("Giving up reconnecting to websocket: {}", e);
+                return None;
+            }
+            Err(e) if attempt >= config.reconnect_policy.max_attempts => {
+                warn!("Giving up reconnecting to websocket after {} attempts: {}", attempt, e);
+                return None;
+            }
+            Err(e) => debug!("Websocket reconnection failed: {}", e),
+        }
+    }
+}
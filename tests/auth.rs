@@ -0,0 +1,163 @@
+mod common;
+
+use common::{builder, client_with, json, MockServer, Request, Response};
+use heatzy::{AuthResponse, Client, MemoryTokenStore, TokenStore};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+fn client(server: &MockServer) -> Client {
+    builder(server)
+        .credentials("user@example.com", "secret")
+        .build()
+        .unwrap()
+}
+
+/// Answer a login with the given token, valid for `lifetime` seconds
+fn login(token: &str, lifetime: i64) -> Response {
+    json(200, json!({"token": token, "uid": "uid", "expire_at": now() + lifetime}))
+}
+
+fn token_invalid() -> Response {
+    json(400, json!({"error_code": 9004, "error_message": "token invalid!"}))
+}
+
+fn devices(request: &Request, valid_token: &str) -> Response {
+    if request.header("X-Gizwits-User-token") == Some(valid_token) {
+        json(200, json!({"devices": []}))
+    } else {
+        token_invalid()
+    }
+}
+
+#[tokio::test]
+async fn logs_in_lazily_and_reuses_the_token() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/app/login" => login("token", 86400),
+        _ => devices(request, "token"),
+    }).await;
+    let client = client(&server);
+
+    client.list_devices().await.unwrap();
+    client.list_devices().await.unwrap();
+
+    assert_eq!(server.count("POST", "/app/login"), 1);
+    assert_eq!(server.count("GET", "/app/bindings"), 2);
+}
+
+#[tokio::test]
+async fn renews_the_token_before_it_expires() {
+    let logins = Arc::new(AtomicUsize::new(0));
+    let counter = logins.clone();
+    let server = MockServer::start(move |request| match request.path.as_str() {
+        // The first token expires within the renewal margin, the second does not
+        "/app/login" => match counter.fetch_add(1, Ordering::SeqCst) {
+            0 => login("first", 60),
+            _ => login("second", 86400),
+        },
+        _ => json(200, json!({"devices": []})),
+    }).await;
+    let client = client(&server);
+
+    client.list_devices().await.unwrap();
+    client.list_devices().await.unwrap();
+    client.list_devices().await.unwrap();
+
+    assert_eq!(logins.load(Ordering::SeqCst), 2);
+    let tokens: Vec<String> = server.requests().iter()
+        .filter(|request| request.path == "/app/bindings")
+        .map(|request| request.header("X-Gizwits-User-token").unwrap().to_string())
+        .collect();
+    assert_eq!(tokens, ["first", "second", "second"]);
+}
+
+#[tokio::test]
+async fn logs_in_again_once_when_the_token_is_rejected() {
+    let logins = Arc::new(AtomicUsize::new(0));
+    let counter = logins.clone();
+    let server = MockServer::start(move |request| match request.path.as_str() {
+        "/app/login" => match counter.fetch_add(1, Ordering::SeqCst) {
+            0 => login("revoked", 86400),
+            _ => login("fresh", 86400),
+        },
+        _ => devices(request, "fresh"),
+    }).await;
+    let client = client(&server);
+
+    client.list_devices().await.unwrap();
+
+    assert_eq!(logins.load(Ordering::SeqCst), 2);
+    assert_eq!(server.count("GET", "/app/bindings"), 2);
+    assert_eq!(client.session().await.unwrap().token, "fresh");
+}
+
+#[tokio::test]
+async fn gives_up_when_the_new_token_is_rejected_too() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/app/login" => login("token", 86400),
+        _ => token_invalid(),
+    }).await;
+    let client = client(&server);
+
+    let error = client.list_devices().await.unwrap_err();
+
+    assert!(error.is_token_invalid(), "unexpected error: {}", error);
+    assert_eq!(server.count("POST", "/app/login"), 2);
+    assert_eq!(server.count("GET", "/app/bindings"), 2);
+}
+
+#[tokio::test]
+async fn logs_in_with_the_new_password_after_changing_it() {
+    let password = Arc::new(std::sync::Mutex::new("secret".to_string()));
+    let current = password.clone();
+    let server = MockServer::start(move |request| match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/app/login") => {
+            let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+            if body["password"] == json!(*current.lock().unwrap()) {
+                login(&format!("token-{}", body["password"].as_str().unwrap()), 86400)
+            } else {
+                json(400, json!({"error_code": 9020, "error_message": "username or password error!"}))
+            }
+        }
+        ("PUT", "/app/users") => {
+            // Changing the password revokes the tokens issued before
+            *current.lock().unwrap() = "changed".to_string();
+            json(200, json!({"uid": "uid"}))
+        }
+        _ => devices(request, "token-changed"),
+    }).await;
+    let client = client(&server);
+
+    client.change_password("secret", "changed").await.unwrap();
+    client.list_devices().await.unwrap();
+
+    assert_eq!(server.count("POST", "/app/login"), 2);
+    assert_eq!(client.session().await.unwrap().token, "token-changed");
+}
+
+#[tokio::test]
+async fn changing_the_password_without_credentials_drops_the_session() {
+    let server = MockServer::start(|_| json(200, json!({"uid": "uid"}))).await;
+    let token_store = Arc::new(MemoryTokenStore::new());
+    let client = client_with(&server, |builder| builder
+        .token_store(token_store.clone())
+        .account("user@example.com"));
+    let key = client.token_key("user@example.com");
+    token_store.save(&key, &AuthResponse {
+        token: "token".to_string(),
+        uid: "uid".to_string(),
+        expire_at: now() + 86400,
+    }).unwrap();
+
+    client.change_password("secret", "changed").await.unwrap();
+
+    assert_eq!(server.count("PUT", "/app/users"), 1);
+    assert_eq!(server.count("POST", "/app/login"), 0);
+    assert!(client.session().await.is_none());
+    assert!(token_store.load(&key).unwrap().is_none());
+}
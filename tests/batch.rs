@@ -0,0 +1,59 @@
+mod common;
+
+use common::{client, client_with, json, MockServer, Response};
+use heatzy::DeviceMode;
+use serde_json::json;
+
+fn device(did: &str, online: bool) -> serde_json::Value {
+    json!({"did": did, "product_name": "Pilote_SoC", "mac": "aabbccddeeff", "is_online": online})
+}
+
+/// Account with `dev1` online and `dev2` offline
+fn account(path: &str) -> Response {
+    match path {
+        "/app/bindings" => json(200, json!({"devices": [device("dev1", true), device("dev2", false)]})),
+        "/app/devices/dev1" => json(200, device("dev1", true)),
+        "/app/devices/dev2" => json(200, device("dev2", false)),
+        "/app/control/dev2" => json(400, json!({"error_code": 9017, "error_message": "device offline!"})),
+        "/app/group/group1/devices" => json(200, json!([{"did": "dev1"}, {"did": "dev2"}])),
+        _ => json(200, json!({})),
+    }
+}
+
+#[tokio::test]
+async fn batches_do_not_list_devices_by_default() {
+    let server = MockServer::start(|request| account(&request.path)).await;
+    let client = client(&server);
+
+    let report = client.set_modes([("dev1", DeviceMode::Eco), ("dev2", DeviceMode::Eco)]).await;
+
+    assert_eq!(server.count("GET", "/app/bindings"), 0);
+    assert_eq!(report.successes.len(), 1);
+    assert_eq!(report.skipped_offline, vec!["dev2".to_string()]);
+    assert!(report.failures.is_empty());
+}
+
+#[tokio::test]
+async fn batches_skip_offline_devices_upfront_when_asked() {
+    let server = MockServer::start(|request| account(&request.path)).await;
+    let client = client_with(&server, |builder| builder.check_online(true));
+
+    let report = client.set_modes([("dev1", DeviceMode::Eco), ("dev2", DeviceMode::Eco)]).await;
+
+    assert!(server.count("GET", "/app/bindings") > 0);
+    assert_eq!(server.count("POST", "/app/control/dev2"), 0);
+    assert_eq!(report.skipped_offline, vec!["dev2".to_string()]);
+}
+
+#[tokio::test]
+async fn group_mode_reports_each_member_in_order() {
+    let server = MockServer::start(|request| account(&request.path)).await;
+    let client = client(&server);
+
+    let outcomes = client.set_group_mode("group1", DeviceMode::Comfort).await.unwrap();
+
+    let dids: Vec<&str> = outcomes.iter().map(|outcome| outcome.did.as_str()).collect();
+    assert_eq!(dids, ["dev1", "dev2"]);
+    assert!(outcomes[0].result.is_ok());
+    assert!(outcomes[1].result.as_ref().unwrap_err().is_device_offline());
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::{client, client_with, json, MockServer, Request, Response};
+use heatzy::{DeviceBinding, HeatzyError};
+use serde_json::{json, Value};
+
+fn body(request: &Request) -> Value {
+    serde_json::from_str(&request.body).unwrap()
+}
+
+/// Report `dev2` as failed and every other device as done
+fn binding_api(request: &Request) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/app/bind_mac") => json(201, json!({"did": "dev1", "mac": "accf23000001", "product_key": "pk"})),
+        ("POST", "/app/bindings") | ("DELETE", "/app/bindings") => {
+            let (success, failed): (Vec<Value>, Vec<Value>) = body(request)["devices"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|device| device["did"].clone())
+                .partition(|did| did != "dev2");
+            json(200, json!({"success": success, "failed": failed}))
+        }
+        ("PUT", "/app/bindings/dev1") => json(200, json!({})),
+        _ => json(404, json!({})),
+    }
+}
+
+#[tokio::test]
+async fn binds_by_mac_with_a_signed_request() {
+    let server = MockServer::start(binding_api).await;
+    let client = client_with(&server, |builder| builder.product_secret("pk", "secret"));
+
+    let device = client.bind_by_mac("pk", "accf23000001").await.unwrap();
+
+    assert_eq!(device.did, "dev1");
+    let request = &server.requests()[0];
+    assert_eq!(body(request), json!({"product_key": "pk", "mac": "accf23000001"}));
+    let timestamp = request.header("X-Gizwits-Timestamp").unwrap();
+    assert!(timestamp.parse::<u64>().is_ok(), "timestamp: {}", timestamp);
+    let expected = format!("{:x}", md5::compute(format!("secret{}", timestamp)));
+    assert_eq!(request.header("X-Gizwits-Signature"), Some(expected.as_str()));
+    assert_eq!(request.header("X-Gizwits-User-token"), Some("token"));
+}
+
+#[tokio::test]
+async fn binding_by_mac_needs_the_product_secret() {
+    let server = MockServer::start(binding_api).await;
+
+    let error = client(&server).bind_by_mac("pk", "accf23000001").await.unwrap_err();
+
+    assert!(matches!(error, HeatzyError::Config(_)), "unexpected error: {}", error);
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn reports_partially_failed_bindings() {
+    let server = MockServer::start(binding_api).await;
+    let client = client(&server);
+    let binding = |did: &str| DeviceBinding {
+        did: did.to_string(),
+        passcode: "passcode".to_string(),
+        alias: Some("Bedroom".to_string()),
+        remark: None,
+    };
+
+    let report = client.bind_devices(&[binding("dev1"), binding("dev2")]).await.unwrap();
+
+    assert_eq!(report.success, ["dev1"]);
+    assert_eq!(report.failed, ["dev2"]);
+    assert!(!report.all_succeeded());
+    assert_eq!(body(&server.requests()[0])["devices"][0], json!({"did": "dev1", "passcode": "passcode", "dev_alias": "Bedroom"}));
+
+    assert!(client.bind_device("dev2", "passcode", None, None).await.is_err());
+}
+
+#[tokio::test]
+async fn reports_partially_failed_unbindings() {
+    let server = MockServer::start(binding_api).await;
+
+    let report = client(&server).unbind_devices(&["dev1", "dev2"]).await.unwrap();
+
+    assert_eq!(report.success, ["dev1"]);
+    assert_eq!(report.failed, ["dev2"]);
+    let request = &server.requests()[0];
+    assert_eq!(request.method, "DELETE");
+    assert_eq!(body(request), json!({"devices": [{"did": "dev1"}, {"did": "dev2"}]}));
+}
+
+#[tokio::test]
+async fn renames_a_device() {
+    let server = MockServer::start(binding_api).await;
+
+    client(&server).rename_device("dev1", "Bedroom").await.unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!((request.method.as_str(), request.path.as_str()), ("PUT", "/app/bindings/dev1"));
+    assert_eq!(body(request), json!({"dev_alias": "Bedroom"}));
+}
+
+#[tokio::test]
+async fn sets_the_remark_of_a_device() {
+    let server = MockServer::start(binding_api).await;
+
+    client(&server).set_device_remark("dev1", "Upstairs").await.unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!((request.method.as_str(), request.path.as_str()), ("PUT", "/app/bindings/dev1"));
+    assert_eq!(body(request), json!({"remark": "Upstairs"}));
+}
+
+#[tokio::test]
+async fn updating_an_unknown_device_is_not_found() {
+    let server = MockServer::start(binding_api).await;
+    let client = client(&server);
+
+    assert!(matches!(client.rename_device("dev9", "Bedroom").await, Err(HeatzyError::NotFound(_))));
+    assert!(matches!(client.set_device_remark("dev9", "Upstairs").await, Err(HeatzyError::NotFound(_))));
+}
@@ -0,0 +1,174 @@
+//! Local stand-in for the Gizwits REST API, answering requests with a handler
+#![allow(dead_code)]
+
+use heatzy::{Client, ClientBuilder, RetryPolicy};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Request received by the stand-in server
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Response sent by the stand-in server
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+pub fn json(status: u16, body: Value) -> Response {
+    Response {
+        status,
+        body: body.to_string(),
+    }
+}
+
+/// Builder pointed at the server, without retries
+pub fn builder(server: &MockServer) -> ClientBuilder {
+    Client::builder()
+        .base_url(&server.url)
+        .retry_policy(RetryPolicy::none())
+}
+
+/// Client of the server using a fixed token
+pub fn client(server: &MockServer) -> Client {
+    client_with(server, |builder| builder)
+}
+
+/// Client of the server using a fixed token, with other settings than [`builder`]
+pub fn client_with(server: &MockServer, configure: impl FnOnce(ClientBuilder) -> ClientBuilder) -> Client {
+    let mut client = configure(builder(server)).build().unwrap();
+    client.set_token("token".to_string());
+    client
+}
+
+pub struct MockServer {
+    /// Base URL to give to the client
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    /// Serve requests with the handler until the test ends
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/app", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (handler, recorded) = (handler.clone(), recorded.clone());
+                tokio::spawn(async move {
+                    serve(stream, handler.as_ref(), &recorded).await;
+                });
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Requests received so far, in order
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Number of requests received on a path
+    pub fn count(&self, method: &str, path: &str) -> usize {
+        self.requests().iter()
+            .filter(|request| request.method == method && request.path == path)
+            .count()
+    }
+}
+
+async fn serve<F>(mut stream: TcpStream, handler: &F, recorded: &Mutex<Vec<Request>>)
+where
+    F: Fn(&Request) -> Response,
+{
+    let mut buffer = Vec::new();
+    let header_end = loop {
+        let mut chunk = [0u8; 4096];
+        let read = stream.read(&mut chunk).await.unwrap();
+        if read == 0 {
+            return;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap().split(' ');
+    let method = request_line.next().unwrap().to_string();
+    let target = request_line.next().unwrap().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let length: usize = headers.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.parse().unwrap())
+        .unwrap_or(0);
+    while buffer.len() < header_end + length {
+        let mut chunk = [0u8; 4096];
+        let read = stream.read(&mut chunk).await.unwrap();
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let request = Request {
+        method,
+        path: path.to_string(),
+        query: query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+        headers,
+        body: String::from_utf8_lossy(&buffer[header_end..]).to_string(),
+    };
+
+    let response = handler(&request);
+    recorded.lock().unwrap().push(request);
+
+    let reply = format!(
+        "HTTP/1.1 {} Stand-in\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    );
+    let _ = stream.write_all(reply.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{client, json, MockServer, Response};
+use heatzy::HeatzyError;
+use serde_json::json;
+use std::time::Duration;
+
+/// Device `dev1` with the given product name
+fn device(path: &str, product_name: &str) -> Response {
+    match path {
+        "/app/devices/dev1" => json(200, json!({
+            "did": "dev1",
+            "product_name": product_name,
+            "mac": "aabbccddeeff",
+            "is_online": true,
+        })),
+        _ => json(200, json!({})),
+    }
+}
+
+#[tokio::test]
+async fn boost_outside_the_product_range_is_rejected() {
+    let server = MockServer::start(|request| device(&request.path, "Pilote_SoC")).await;
+    let client = client(&server);
+
+    for duration in [Duration::ZERO, Duration::from_secs(256 * 60)] {
+        let error = client.set_boost("dev1", duration).await.unwrap_err();
+        assert!(matches!(error, HeatzyError::InvalidValue(_)), "unexpected error: {}", error);
+    }
+    assert_eq!(server.count("POST", "/app/control/dev1"), 0);
+
+    client.set_boost("dev1", Duration::from_secs(90 * 60)).await.unwrap();
+    let control = server.requests().into_iter()
+        .find(|request| request.path == "/app/control/dev1")
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&control.body).unwrap();
+    assert_eq!(body["attrs"]["derog_time"], 90);
+}
+
+#[tokio::test]
+async fn vacation_outside_the_product_range_is_rejected() {
+    let server = MockServer::start(|request| device(&request.path, "Pilote_SoC")).await;
+    let client = client(&server);
+
+    for days in [0, 256] {
+        let error = client.set_vacation("dev1", days).await.unwrap_err();
+        assert!(matches!(error, HeatzyError::InvalidValue(_)), "unexpected error: {}", error);
+    }
+    assert_eq!(server.count("POST", "/app/control/dev1"), 0);
+}
+
+#[tokio::test]
+async fn derogations_are_rejected_on_unsupported_products() {
+    let server = MockServer::start(|request| device(&request.path, "Pilote2")).await;
+    let client = client(&server);
+
+    let error = client.set_boost("dev1", Duration::from_secs(60 * 60)).await.unwrap_err();
+    assert!(matches!(error, HeatzyError::Unsupported(_)), "unexpected error: {}", error);
+
+    let error = client.set_vacation("dev1", 7).await.unwrap_err();
+    assert!(matches!(error, HeatzyError::Unsupported(_)), "unexpected error: {}", error);
+
+    assert_eq!(server.count("POST", "/app/control/dev1"), 0);
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{client, json, MockServer, Request, Response};
+use heatzy::HeatzyError;
+use serde_json::{json, Value};
+
+fn body(request: &Request) -> Value {
+    serde_json::from_str(&request.body).unwrap()
+}
+
+/// Account with the single group `group1`, where `dev2` cannot be added or removed
+fn group_api(request: &Request) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/app/group") => json(200, json!([
+            {"id": "group1", "group_name": "Upstairs", "product_key": "pk", "verbose_name": "x"},
+        ])),
+        ("POST", "/app/group") => json(201, json!({"id": "group2"})),
+        ("PUT", "/app/group/group1") | ("DELETE", "/app/group/group1") => json(200, json!({})),
+        ("GET", "/app/group/group1/devices") => json(200, json!([{"did": "dev1", "dev_alias": "Bedroom"}])),
+        ("POST", "/app/group/group1/devices") | ("DELETE", "/app/group/group1/devices") => {
+            let (success, failed): (Vec<Value>, Vec<Value>) = body(request)["dids"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .cloned()
+                .partition(|did| did != "dev2");
+            json(200, json!({"success": success, "failed": failed}))
+        }
+        _ => json(404, json!({})),
+    }
+}
+
+#[tokio::test]
+async fn lists_groups() {
+    let server = MockServer::start(group_api).await;
+
+    let groups = client(&server).list_groups().await.unwrap();
+
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].id, "group1");
+    assert_eq!(groups[0].name, "Upstairs");
+    assert_eq!(groups[0].product_key.as_deref(), Some("pk"));
+    assert_eq!(groups[0].extra["verbose_name"], json!("x"));
+}
+
+#[tokio::test]
+async fn creates_renames_and_deletes_a_group() {
+    let server = MockServer::start(group_api).await;
+    let client = client(&server);
+
+    assert_eq!(client.create_group("Downstairs", Some("pk")).await.unwrap(), "group2");
+    client.rename_group("group1", "First floor").await.unwrap();
+    client.delete_group("group1").await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(body(&requests[0]), json!({"group_name": "Downstairs", "product_key": "pk"}));
+    assert_eq!((requests[1].method.as_str(), requests[1].path.as_str()), ("PUT", "/app/group/group1"));
+    assert_eq!(body(&requests[1]), json!({"group_name": "First floor"}));
+    assert_eq!((requests[2].method.as_str(), requests[2].path.as_str()), ("DELETE", "/app/group/group1"));
+}
+
+#[tokio::test]
+async fn updates_members_with_a_per_device_report() {
+    let server = MockServer::start(group_api).await;
+    let client = client(&server);
+
+    let added = client.add_group_devices("group1", &["dev1", "dev2"]).await.unwrap();
+    let removed = client.remove_group_devices("group1", &["dev1"]).await.unwrap();
+    let members = client.list_group_devices("group1").await.unwrap();
+
+    assert_eq!(added.success, ["dev1"]);
+    assert_eq!(added.failed, ["dev2"]);
+    assert!(removed.all_succeeded());
+    assert_eq!(members[0].did, "dev1");
+    let requests = server.requests();
+    assert_eq!(requests[0].query("show_result"), Some("1"));
+    assert_eq!(body(&requests[0]), json!({"dids": ["dev1", "dev2"]}));
+    assert_eq!(requests[1].method, "DELETE");
+}
+
+#[tokio::test]
+async fn unknown_groups_are_not_found() {
+    let server = MockServer::start(group_api).await;
+    let client = client(&server);
+
+    assert!(matches!(client.rename_group("group9", "x").await, Err(HeatzyError::NotFound(_))));
+    assert!(matches!(client.delete_group("group9").await, Err(HeatzyError::NotFound(_))));
+    assert!(matches!(client.list_group_devices("group9").await, Err(HeatzyError::NotFound(_))));
+    assert!(matches!(client.add_group_devices("group9", &["dev1"]).await, Err(HeatzyError::NotFound(_))));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::{client, json, MockServer, Request, Response};
+use futures::TryStreamExt;
+use heatzy::{ListDevicesOptions, SharingRole};
+use serde_json::json;
+use std::time::Duration;
+
+const DEVICES: usize = 250;
+
+fn device(index: usize) -> serde_json::Value {
+    json!({
+        "did": format!("dev{}", index),
+        "dev_alias": format!("Device {}", index),
+        "product_key": "51d16c22a5f74280bc3cfe9ebcdc6402",
+        "product_name": "Heatzy Pilote",
+        "mac": "accf00000000",
+        "is_online": true,
+    })
+}
+
+/// Serve `DEVICES` devices, returning at most `max_limit` per page
+fn page(request: &Request, max_limit: usize) -> Response {
+    let limit: usize = request.query("limit").unwrap().parse().unwrap();
+    let skip: usize = request.query("skip").unwrap().parse().unwrap();
+    let devices: Vec<_> = (skip..DEVICES).take(limit.min(max_limit)).map(device).collect();
+    json(200, json!({"devices": devices}))
+}
+
+fn options(page_size: u32) -> ListDevicesOptions {
+    ListDevicesOptions {
+        page_size,
+        ..ListDevicesOptions::default()
+    }
+}
+
+fn skips(server: &MockServer) -> Vec<String> {
+    server.requests().iter().map(|request| request.query("skip").unwrap().to_string()).collect()
+}
+
+#[tokio::test]
+async fn fetches_every_page() {
+    let server = MockServer::start(|request| page(request, usize::MAX)).await;
+
+    let devices = client(&server).list_devices_with(options(100)).await.unwrap();
+
+    assert_eq!(devices.len(), DEVICES);
+    assert_eq!(devices[DEVICES - 1].did, format!("dev{}", DEVICES - 1));
+    assert_eq!(skips(&server), ["0", "100", "200", "250"]);
+}
+
+#[tokio::test]
+async fn keeps_going_when_the_server_caps_the_page_size() {
+    let server = MockServer::start(|request| page(request, 40)).await;
+
+    let devices = client(&server).list_devices_with(options(100)).await.unwrap();
+
+    assert_eq!(devices.len(), DEVICES);
+    assert_eq!(skips(&server), ["0", "40", "80", "120", "160", "200", "240", "250"]);
+}
+
+#[tokio::test]
+async fn stops_when_the_server_ignores_skip() {
+    let server = MockServer::start(|_| {
+        let devices: Vec<_> = (0..10).map(device).collect();
+        json(200, json!({"devices": devices}))
+    }).await;
+    let client = client(&server);
+
+    let devices: Vec<_> = tokio::time::timeout(Duration::from_secs(5), client.list_devices_stream(options(10)).try_collect::<Vec<_>>())
+        .await
+        .expect("paging did not stop")
+        .unwrap();
+
+    assert_eq!(devices.len(), 10);
+    assert_eq!(skips(&server), ["0", "10"]);
+}
+
+#[tokio::test]
+async fn empty_account_takes_one_request() {
+    let server = MockServer::start(|_| json(200, json!({"devices": []}))).await;
+
+    let devices = client(&server).list_devices().await.unwrap();
+
+    assert!(devices.is_empty());
+    assert_eq!(server.requests().len(), 1);
+}
+
+fn sharing(index: usize) -> serde_json::Value {
+    json!({"id": index, "did": format!("dev{}", index), "status": 0})
+}
+
+#[tokio::test]
+async fn sharings_keep_going_when_the_server_caps_the_page_size() {
+    let server = MockServer::start(|request| {
+        let skip: usize = request.query("skip").unwrap().parse().unwrap();
+        let sharings: Vec<_> = (skip..150).take(30).map(sharing).collect();
+        json(200, json!({"objects": sharings}))
+    }).await;
+
+    let sharings = client(&server).list_sharings(SharingRole::Owner).await.unwrap();
+
+    assert_eq!(sharings.len(), 150);
+    assert_eq!(skips(&server), ["0", "30", "60", "90", "120", "150"]);
+}
+
+#[tokio::test]
+async fn sharings_stop_when_the_server_ignores_skip() {
+    let server = MockServer::start(|_| {
+        let sharings: Vec<_> = (0..100).map(sharing).collect();
+        json(200, json!({"objects": sharings}))
+    }).await;
+
+    let sharings = client(&server).list_sharings(SharingRole::Guest).await.unwrap();
+
+    assert_eq!(sharings.len(), 100);
+    assert_eq!(skips(&server), ["0", "100"]);
+}
+
+fn task(index: usize) -> serde_json::Value {
+    let repeat = if index == 7 { "fortnightly" } else { "mon,fri" };
+    json!({"id": format!("task{}", index), "time": "06:30", "repeat": repeat, "task": []})
+}
+
+#[tokio::test]
+async fn scheduler_tasks_keep_going_when_the_server_caps_the_page_size() {
+    let server = MockServer::start(|request| {
+        let skip: usize = request.query("skip").unwrap().parse().unwrap();
+        let tasks: Vec<_> = (skip..120).take(50).map(task).collect();
+        json(200, json!(tasks))
+    }).await;
+
+    let tasks = client(&server).list_scheduled_tasks().await.unwrap();
+
+    // The task with an unknown repeat is skipped
+    assert_eq!(tasks.len(), 119);
+    assert!(tasks.iter().all(|task| task.id != "task7"));
+    assert_eq!(skips(&server), ["0", "50", "100", "120"]);
+}
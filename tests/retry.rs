@@ -0,0 +1,44 @@
+mod common;
+
+use common::{client_with, json, MockServer};
+use heatzy::{Client, ControlAttributes, RetryPolicy};
+use serde_json::json;
+use std::time::Duration;
+
+fn client(server: &MockServer) -> Client {
+    client_with(server, |builder| builder.retry_policy(RetryPolicy {
+        initial_backoff: Duration::from_millis(1),
+        jitter: 0.0,
+        ..RetryPolicy::default()
+    }))
+}
+
+#[tokio::test]
+async fn idempotent_requests_are_retried_on_server_errors() {
+    let server = MockServer::start(|_| json(503, json!({}))).await;
+    let client = client(&server);
+
+    client.control("dev1", &ControlAttributes::new().with("mode", 1)).await.unwrap_err();
+
+    assert_eq!(server.count("POST", "/app/control/dev1"), 3);
+}
+
+#[tokio::test]
+async fn non_idempotent_requests_are_not_retried_on_server_errors() {
+    let server = MockServer::start(|_| json(503, json!({}))).await;
+    let client = client(&server);
+
+    client.bind_device("dev1", "passcode", None, None).await.unwrap_err();
+
+    assert_eq!(server.count("POST", "/app/bindings"), 1);
+}
+
+#[tokio::test]
+async fn throttled_requests_are_retried_even_when_not_idempotent() {
+    let server = MockServer::start(|_| json(429, json!({}))).await;
+    let client = client(&server);
+
+    client.bind_device("dev1", "passcode", None, None).await.unwrap_err();
+
+    assert_eq!(server.count("POST", "/app/bindings"), 3);
+}
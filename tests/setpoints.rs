@@ -0,0 +1,68 @@
+mod common;
+
+use common::{client, json, MockServer, Response};
+use heatzy::{HeatzyError, Setpoints, Temperature};
+use serde_json::json;
+
+/// Glow device with comfort at 20.0 °C and eco at 17.0 °C
+fn glow(path: &str) -> Response {
+    match path {
+        "/app/devices/dev1" => json(200, json!({
+            "did": "dev1",
+            "product_name": "Glow",
+            "mac": "aabbccddeeff",
+            "is_online": true,
+        })),
+        "/app/devdata/dev1/latest" => json(200, json!({
+            "did": "dev1",
+            "updated_at": 1,
+            "attr": {"cft_tempH": 0, "cft_tempL": 200, "eco_tempH": 0, "eco_tempL": 170},
+        })),
+        _ => json(200, json!({})),
+    }
+}
+
+#[tokio::test]
+async fn single_eco_setpoint_above_current_comfort_is_rejected() {
+    let server = MockServer::start(|request| glow(&request.path)).await;
+    let client = client(&server);
+
+    let error = client.set_setpoints("dev1", Setpoints {
+        comfort: None,
+        eco: Some(Temperature::from_celsius(25.0)),
+    }).await.unwrap_err();
+
+    assert!(matches!(error, HeatzyError::InvalidValue(_)), "unexpected error: {}", error);
+    assert_eq!(server.count("POST", "/app/control/dev1"), 0);
+}
+
+#[tokio::test]
+async fn single_eco_setpoint_below_current_comfort_is_sent() {
+    let server = MockServer::start(|request| glow(&request.path)).await;
+    let client = client(&server);
+
+    client.set_setpoints("dev1", Setpoints {
+        comfort: None,
+        eco: Some(Temperature::from_celsius(18.5)),
+    }).await.unwrap();
+
+    let control = server.requests().into_iter()
+        .find(|request| request.path == "/app/control/dev1")
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&control.body).unwrap();
+    assert_eq!(body, json!({"attrs": {"eco_tempH": 0, "eco_tempL": 185}}));
+}
+
+#[tokio::test]
+async fn both_setpoints_are_checked_without_reading_the_device() {
+    let server = MockServer::start(|request| glow(&request.path)).await;
+    let client = client(&server);
+
+    client.set_setpoints("dev1", Setpoints {
+        comfort: Some(Temperature::from_celsius(19.0)),
+        eco: Some(Temperature::from_celsius(19.5)),
+    }).await.unwrap_err();
+
+    assert_eq!(server.count("GET", "/app/devdata/dev1/latest"), 0);
+    assert_eq!(server.count("POST", "/app/control/dev1"), 0);
+}
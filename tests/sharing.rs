@@ -0,0 +1,74 @@
+mod common;
+
+use common::{client, json, MockServer, Request, Response};
+use heatzy::{HeatzyError, ShareTarget, SharingRole, SharingStatus};
+use serde_json::json;
+
+fn sharing_api(request: &Request) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/app/sharing") => json(201, json!({"id": 12})),
+        ("GET", "/app/sharing") if request.query("skip") == Some("0") => json(200, json!({"objects": [
+            {"id": 12, "did": "dev1", "status": 0, "user_name": "bob"},
+            {"id": "13", "did": "dev2", "status": 1},
+        ]})),
+        ("GET", "/app/sharing") => json(200, json!({"objects": []})),
+        ("PUT", "/app/sharing/12") | ("DELETE", "/app/sharing/12") => json(200, json!({})),
+        _ => json(404, json!({})),
+    }
+}
+
+#[tokio::test]
+async fn creates_a_sharing() {
+    let server = MockServer::start(sharing_api).await;
+
+    let invitation = client(&server)
+        .create_sharing("dev1", &ShareTarget::Email("bob@example.com".to_string()))
+        .await
+        .unwrap();
+
+    assert_eq!(invitation.id, "12");
+    let request = &server.requests()[0];
+    let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(body, json!({"type": 0, "did": "dev1", "email": "bob@example.com"}));
+}
+
+#[tokio::test]
+async fn lists_sharings_of_a_role() {
+    let server = MockServer::start(sharing_api).await;
+
+    let sharings = client(&server).list_sharings(SharingRole::Guest).await.unwrap();
+
+    assert_eq!(sharings.iter().map(|sharing| sharing.id.as_str()).collect::<Vec<_>>(), ["12", "13"]);
+    assert_eq!(sharings[0].status, SharingStatus::Pending);
+    assert_eq!(sharings[0].user_name.as_deref(), Some("bob"));
+    assert_eq!(server.requests()[0].query("sharing_type"), Some("1"));
+}
+
+#[tokio::test]
+async fn accepts_refuses_and_revokes_sharings() {
+    let server = MockServer::start(sharing_api).await;
+    let client = client(&server);
+
+    client.accept_sharing("12").await.unwrap();
+    client.refuse_sharing("12").await.unwrap();
+    client.revoke_sharing("12").await.unwrap();
+
+    let requests = server.requests();
+    let calls: Vec<_> = requests.iter()
+        .map(|request| (request.method.as_str(), request.path.as_str(), request.query("on")))
+        .collect();
+    assert_eq!(calls, [
+        ("PUT", "/app/sharing/12", Some("1")),
+        ("PUT", "/app/sharing/12", Some("0")),
+        ("DELETE", "/app/sharing/12", None),
+    ]);
+}
+
+#[tokio::test]
+async fn unknown_sharings_are_not_found() {
+    let server = MockServer::start(sharing_api).await;
+    let client = client(&server);
+
+    assert!(matches!(client.accept_sharing("99").await, Err(HeatzyError::NotFound(_))));
+    assert!(matches!(client.revoke_sharing("99").await, Err(HeatzyError::NotFound(_))));
+}
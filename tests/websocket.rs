@@ -0,0 +1,252 @@
+use futures::{SinkExt, StreamExt};
+use heatzy::{ControlAttributes, DeviceEvent, GizwitsErrorCode, HeatzyError, RetryPolicy, WebSocketSession};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Local stand-in for the Gizwits websocket, forwarding the client messages to the test
+async fn stand_in_server() -> (String, TcpListener) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws/app/v1", listener.local_addr().unwrap());
+    (url, listener)
+}
+
+async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+    let (stream, _) = listener.accept().await.unwrap();
+    tokio_tungstenite::accept_async(stream).await.unwrap()
+}
+
+async fn receive(socket: &mut WebSocketStream<TcpStream>) -> Value {
+    loop {
+        match tokio::time::timeout(TIMEOUT, socket.next()).await.unwrap() {
+            Some(Ok(Message::Text(text))) => return serde_json::from_str(&text).unwrap(),
+            Some(Ok(_)) => continue,
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+}
+
+async fn reply(socket: &mut WebSocketStream<TcpStream>, message: Value) {
+    socket.send(Message::Text(message.to_string())).await.unwrap();
+}
+
+/// Answer the login and return the login request
+async fn login(socket: &mut WebSocketStream<TcpStream>, success: bool) -> Value {
+    let request = receive(socket).await;
+    assert_eq!(request["cmd"], "login_req");
+    reply(socket, json!({"cmd": "login_res", "data": {"success": success}})).await;
+    request
+}
+
+async fn next_event(session: &mut WebSocketSession) -> DeviceEvent {
+    tokio::time::timeout(TIMEOUT, session.next()).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn logs_in_subscribes_and_streams_events() {
+    let (url, listener) = stand_in_server().await;
+    let (sent, mut received) = mpsc::unbounded_channel();
+
+    let server = tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        sent.send(login(&mut socket, true).await).unwrap();
+        sent.send(receive(&mut socket).await).unwrap();
+
+        reply(&mut socket, json!({"cmd": "s2c_noti", "data": {"did": "dev1", "attrs": {"mode": "eco"}}})).await;
+        reply(&mut socket, json!({"cmd": "s2c_online_status", "data": {"did": "dev1", "online": false}})).await;
+
+        let write = receive(&mut socket).await;
+        sent.send(write).unwrap();
+        socket
+    });
+
+    let mut session = WebSocketSession::builder("app", "uid", "token")
+        .url(url)
+        .devices(["dev1"])
+        .connect()
+        .await
+        .unwrap();
+
+    let login = received.recv().await.unwrap();
+    assert_eq!(login["data"]["appid"], "app");
+    assert_eq!(login["data"]["uid"], "uid");
+    assert_eq!(login["data"]["token"], "token");
+    assert_eq!(login["data"]["auto_subscribe"], false);
+    let subscribe = received.recv().await.unwrap();
+    assert_eq!(subscribe, json!({"cmd": "subscribe_req", "data": [{"did": "dev1"}]}));
+
+    let mut attrs = serde_json::Map::new();
+    attrs.insert("mode".to_string(), json!("eco"));
+    assert_eq!(next_event(&mut session).await, DeviceEvent::Attributes { did: "dev1".to_string(), attrs });
+    assert_eq!(next_event(&mut session).await, DeviceEvent::Connectivity { did: "dev1".to_string(), online: false });
+
+    session.write("dev1", &ControlAttributes::new().with("mode", 1)).await.unwrap();
+    let write = received.recv().await.unwrap();
+    assert_eq!(write, json!({"cmd": "c2s_write", "data": {"did": "dev1", "attrs": {"mode": 1}}}));
+
+    server.await.unwrap();
+    session.close().await;
+}
+
+#[tokio::test]
+async fn rejected_login_fails_to_connect() {
+    let (url, listener) = stand_in_server().await;
+    tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        login(&mut socket, false).await;
+        let _ = socket.next().await;
+    });
+
+    let result = WebSocketSession::builder("app", "uid", "token").url(url).connect().await;
+    assert!(matches!(result, Err(HeatzyError::Auth(_))));
+}
+
+#[tokio::test]
+async fn reconnects_after_connection_loss() {
+    let (url, listener) = stand_in_server().await;
+
+    let server = tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        login(&mut socket, true).await;
+        drop(socket);
+
+        let mut socket = accept(&listener).await;
+        login(&mut socket, true).await;
+        reply(&mut socket, json!({"cmd": "s2c_online_status", "data": {"did": "dev1", "online": true}})).await;
+        socket
+    });
+
+    let mut session = WebSocketSession::builder("app", "uid", "token")
+        .url(url)
+        .reconnect_policy(RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    assert_eq!(next_event(&mut session).await, DeviceEvent::Disconnected);
+    assert_eq!(next_event(&mut session).await, DeviceEvent::Reconnected);
+    assert_eq!(next_event(&mut session).await, DeviceEvent::Connectivity { did: "dev1".to_string(), online: true });
+
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn heartbeat_is_sent_and_answered() {
+    let (url, listener) = stand_in_server().await;
+    let (sent, mut received) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        login(&mut socket, true).await;
+        let ping = receive(&mut socket).await;
+        sent.send(ping).unwrap();
+        reply(&mut socket, json!({"cmd": "pong"})).await;
+        let _ = socket.next().await;
+    });
+
+    let _session = WebSocketSession::builder("app", "uid", "token")
+        .url(url)
+        .heartbeat_interval(Duration::from_secs(1))
+        .connect()
+        .await
+        .unwrap();
+
+    let ping = tokio::time::timeout(TIMEOUT, received.recv()).await.unwrap().unwrap();
+    assert_eq!(ping, json!({"cmd": "ping"}));
+}
+
+#[tokio::test]
+async fn reconnections_log_in_with_a_fresh_token() {
+    let (url, listener) = stand_in_server().await;
+    let (sent, mut received) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        sent.send(login(&mut socket, true).await).unwrap();
+        drop(socket);
+
+        let mut socket = accept(&listener).await;
+        sent.send(login(&mut socket, true).await).unwrap();
+        let _ = socket.next().await;
+    });
+
+    let renewals = Arc::new(AtomicUsize::new(0));
+    let counter = renewals.clone();
+    let mut session = WebSocketSession::builder("app", "uid", "expired")
+        .url(url)
+        .reconnect_policy(RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        })
+        .token_provider(move || {
+            let renewal = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { Ok(format!("renewed-{}", renewal)) }
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    assert_eq!(next_event(&mut session).await, DeviceEvent::Disconnected);
+    assert_eq!(next_event(&mut session).await, DeviceEvent::Reconnected);
+
+    let first = received.recv().await.unwrap();
+    let second = received.recv().await.unwrap();
+    assert_eq!(first["data"]["token"], "expired");
+    assert_eq!(second["data"]["token"], "renewed-1");
+    assert_eq!(renewals.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn reconnecting_stops_when_the_credentials_are_refused() {
+    let (url, listener) = stand_in_server().await;
+
+    tokio::spawn(async move {
+        let mut socket = accept(&listener).await;
+        login(&mut socket, true).await;
+        drop(socket);
+        // Keep accepting so only the refused login ends the session
+        loop {
+            let _ = listener.accept().await;
+        }
+    });
+
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let mut session = WebSocketSession::builder("app", "uid", "token")
+        .url(url)
+        .reconnect_policy(RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        })
+        .token_provider(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                Err(HeatzyError::Gizwits {
+                    status: reqwest::StatusCode::BAD_REQUEST,
+                    code: GizwitsErrorCode::InvalidCredentials,
+                    message: "username or password error!".to_string(),
+                    detail: None,
+                })
+            }
+        })
+        .connect()
+        .await
+        .unwrap();
+
+    assert_eq!(next_event(&mut session).await, DeviceEvent::Disconnected);
+    assert_eq!(tokio::time::timeout(TIMEOUT, session.next()).await.unwrap(), None);
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+}